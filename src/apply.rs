@@ -0,0 +1,433 @@
+use futures::future::{BoxFuture, FutureExt, TryFutureExt};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::{Account, AuthInfo, Clearable, ResolvedProfile};
+use crate::image_source::ImageSource;
+use crate::imaging::{self, ImageKind, ImageProcessing};
+use crate::state::{Face, UploadedImage};
+use crate::template::Variables;
+use crate::twitter_api::{client::TwitterClient, models::User, TwitterError, TwitterResult};
+
+/// the delay before the first retry, doubled on each retry.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// a step of applying the profile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// the profile before applying, to roll back
+    Snapshot,
+    Image,
+    Banner,
+    /// name, url, location and description, which are updated at once
+    Text,
+    Intro,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::Snapshot => write!(f, "snapshot"),
+            Field::Image => write!(f, "image"),
+            Field::Banner => write!(f, "banner"),
+            Field::Text => write!(f, "name/url/location/description"),
+            Field::Intro => write!(f, "intro"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Applied,
+    /// the live profile has been the same already, so nothing is sent
+    Unchanged,
+    Failed(TwitterError),
+    /// applied, and then restored because another step failed
+    RolledBack,
+    /// applied, but restoring it failed
+    RollbackFailed(TwitterError),
+}
+
+/// the results of the steps of applying the profile.
+#[derive(Debug, Default)]
+pub struct ApplyReport {
+    pub results: Vec<(Field, Outcome)>,
+}
+
+impl Outcome {
+    /// whether the field is as the profile says.
+    pub fn is_done(&self) -> bool {
+        matches!(self, Outcome::Applied | Outcome::Unchanged)
+    }
+}
+
+impl ApplyReport {
+    /// whether the profile is changed. the failure of the intro does not matter.
+    pub fn is_applied(&self) -> bool {
+        self.results
+            .iter()
+            .all(|(field, outcome)| *field == Field::Intro || outcome.is_done())
+    }
+
+    fn push(&mut self, field: Field, result: TwitterResult<()>) -> bool {
+        let succeeded = result.is_ok();
+        self.results.push((
+            field,
+            match result {
+                Ok(_) => Outcome::Applied,
+                Err(e) => Outcome::Failed(e),
+            },
+        ));
+        succeeded
+    }
+}
+
+impl fmt::Display for ApplyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (field, outcome) in self.results.iter() {
+            match outcome {
+                Outcome::Applied => writeln!(f, "  {}: applied", field)?,
+                Outcome::Unchanged => writeln!(f, "  {}: unchanged", field)?,
+                Outcome::Failed(e) => writeln!(f, "  {}: failed: {}", field, e)?,
+                Outcome::RolledBack => writeln!(f, "  {}: rolled back", field)?,
+                Outcome::RollbackFailed(e) => {
+                    writeln!(f, "  {}: applied, but rolling back failed: {}", field, e)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ResolvedProfile<'_> {
+    /// apply the fields one by one. the transient failures are retried, and the fields already
+    /// applied are restored if a field fails at last.
+    /// the fields same as the live profile are not sent, nor the images in `face` which are
+    /// still live. `turns` counts the round robins of the images.
+    pub async fn apply(
+        &self,
+        client: &dyn TwitterClient,
+        account: &Account,
+        in_reply_to_status_id: Option<u64>,
+        vars: &Variables,
+        mut face: Option<&mut Face>,
+        turns: &mut BTreeMap<String, u64>,
+    ) -> ApplyReport {
+        let auth_info = self.target_auth_info.unwrap_or_else(|| account.auth_info());
+        let retries = account.property().apply_retries;
+        let mut report = ApplyReport::default();
+        // nothing is changed if any field is invalid
+        let fields = match self.render(vars) {
+            Ok(fields) => fields,
+            Err(e) => {
+                report.push(Field::Text, Err(e.into()));
+                return report;
+            }
+        };
+
+        // pick and process the images before changing anything
+        let processing = account.property().image_processing.as_ref();
+        let mut image = None;
+        if let Some(source) = self.image {
+            let kind = ImageKind::Avatar;
+            match prepare(client, source, kind, processing, turns, self.key).await {
+                Ok(path) => image = Some(path),
+                Err(e) => {
+                    report.push(Field::Image, Err(e));
+                    return report;
+                }
+            }
+        }
+        let mut banner = None;
+        if let Some(source) = self.banner.and_then(Clearable::value) {
+            let kind = ImageKind::Banner;
+            match prepare(client, source, kind, processing, turns, self.key).await {
+                Ok(path) => banner = Some(path),
+                Err(e) => {
+                    report.push(Field::Banner, Err(e));
+                    return report;
+                }
+            }
+        }
+        let clears_banner = self.banner == Some(&Clearable::Clear);
+
+        let snapshot = match retry(retries, || client.verify_credentials(auth_info)).await {
+            Ok(user) => Some(user),
+            // only the profile changed in several steps can be left half-changed
+            Err(e) if image.is_some() || banner.is_some() || clears_banner => {
+                report.push(Field::Snapshot, Err(e));
+                return report;
+            }
+            Err(e) => {
+                println!(
+                    "[WARN] {}: failed to get the current profile, sending all the fields: {}",
+                    account.key, e
+                );
+                None
+            }
+        };
+
+        // the images first, which are likely to fail
+        let mut succeeded = true;
+        if let Some(ref image_path) = image {
+            let live_url = snapshot
+                .as_ref()
+                .and_then(|u| u.profile_image_url.as_deref());
+            succeeded = upload(
+                &mut report,
+                Field::Image,
+                image_path,
+                face.as_deref_mut().map(|f| &mut f.image),
+                live_url,
+                retries,
+                || {
+                    client
+                        .update_profile_image(auth_info, image_path)
+                        .map_ok(|user| user.profile_image_url)
+                        .boxed()
+                },
+            )
+            .await;
+        }
+        if let (true, Some(image_path)) = (succeeded, &banner) {
+            let live_url = snapshot
+                .as_ref()
+                .and_then(|u| u.profile_banner_url.as_deref());
+            succeeded = upload(
+                &mut report,
+                Field::Banner,
+                image_path,
+                face.as_deref_mut().map(|f| &mut f.banner),
+                live_url,
+                retries,
+                || {
+                    async move {
+                        client.update_profile_banner(auth_info, image_path).await?;
+                        // the upload does not tell the URL. not retried as it is uploaded already
+                        Ok(client
+                            .verify_credentials(auth_info)
+                            .await
+                            .ok()
+                            .and_then(|user| user.profile_banner_url))
+                    }
+                    .boxed()
+                },
+            )
+            .await;
+        }
+        if succeeded && clears_banner {
+            if snapshot
+                .as_ref()
+                .is_some_and(|u| u.profile_banner_url.is_none())
+            {
+                report.results.push((Field::Banner, Outcome::Unchanged));
+            } else {
+                let result = retry(retries, || client.remove_profile_banner(auth_info)).await;
+                if let Some(face) = face.as_deref_mut() {
+                    face.banner = None;
+                }
+                succeeded = report.push(Field::Banner, result);
+            }
+        }
+        if succeeded {
+            let mut text = [
+                fields.name.as_deref(),
+                fields.url.as_deref(),
+                fields.location.as_deref(),
+                fields.description.as_deref(),
+            ];
+            if let Some(ref user) = snapshot {
+                let live = [
+                    user.name.clone(),
+                    user.expanded_url().unwrap_or_default(),
+                    user.location.clone().unwrap_or_default(),
+                    user.expanded_description(),
+                ];
+                for (field, live) in text.iter_mut().zip(live.iter()) {
+                    if *field == Some(live.as_str()) {
+                        *field = None;
+                    }
+                }
+                // Twitter adds "http://" to the URL written without the scheme
+                if text[1].is_some_and(|url| without_scheme(url) == without_scheme(&live[1])) {
+                    text[1] = None;
+                }
+            }
+            if text.iter().all(Option::is_none) {
+                report.results.push((Field::Text, Outcome::Unchanged));
+            } else {
+                let [name, url, location, description] = text;
+                let result = retry(retries, || {
+                    client.update_profile(auth_info, name, url, location, description)
+                })
+                .await;
+                succeeded = report.push(Field::Text, result);
+            }
+        }
+        if !succeeded {
+            if let Some(ref user) = snapshot {
+                rollback(client, auth_info, user, retries, &mut report).await;
+            }
+            // the images restored are not the uploaded ones any more
+            if let Some(face) = face {
+                for (field, outcome) in report.results.iter() {
+                    match (field, outcome) {
+                        (Field::Image, Outcome::RolledBack) => face.image = None,
+                        (Field::Banner, Outcome::RolledBack) => face.banner = None,
+                        _ => {}
+                    }
+                }
+            }
+            return report;
+        }
+
+        // say hello as the new profile
+        if let Some(ref intro) = fields.intro {
+            let result = retry(retries, || {
+                client.update_status(auth_info, intro, in_reply_to_status_id)
+            })
+            .await;
+            report.push(Field::Intro, result.map(|_| ()));
+        }
+        report
+    }
+}
+
+fn without_scheme(url: &str) -> &str {
+    url.trim_start_matches("https://")
+        .trim_start_matches("http://")
+}
+
+/// pick the image from the source, and process it.
+async fn prepare(
+    client: &dyn TwitterClient,
+    source: &ImageSource,
+    kind: ImageKind,
+    processing: Option<&ImageProcessing>,
+    turns: &mut BTreeMap<String, u64>,
+    profile: &str,
+) -> TwitterResult<String> {
+    let path = source
+        .fetch(client, kind, processing, turns, profile)
+        .await?;
+    Ok(imaging::prepare(&path, kind, processing)?)
+}
+
+/// upload the image unless the same one is live, and record the uploaded one with its URL,
+/// which `request` returns if known.
+async fn upload<'a, F>(
+    report: &mut ApplyReport,
+    field: Field,
+    path: &str,
+    record: Option<&mut Option<UploadedImage>>,
+    live_url: Option<&str>,
+    retries: u32,
+    request: F,
+) -> bool
+where
+    F: FnMut() -> BoxFuture<'a, TwitterResult<Option<String>>>,
+{
+    // the unreadable file is left to the upload to report
+    let sha1 = std::fs::read(path)
+        .ok()
+        .map(|data| format!("{:x}", Sha1::digest(&data)));
+    let mut record = record;
+    if let (Some(Some(uploaded)), Some(sha1)) = (&mut record, &sha1) {
+        if uploaded.check_live(sha1, live_url) {
+            report.results.push((field, Outcome::Unchanged));
+            return true;
+        }
+    }
+    let result = retry(retries, request).await;
+    if let Some(record) = record {
+        *record = match (&result, sha1) {
+            (Ok(url), Some(sha1)) => Some(UploadedImage {
+                sha1,
+                url: url.clone(),
+            }),
+            _ => None,
+        };
+    }
+    report.push(field, result.map(|_| ()))
+}
+
+/// restore the applied fields to the snapshot, in the reverse order.
+async fn rollback(
+    client: &dyn TwitterClient,
+    auth_info: &AuthInfo,
+    user: &User,
+    retries: u32,
+    report: &mut ApplyReport,
+) {
+    let image_dir = std::env::temp_dir().join(format!("twentyfaces-{}", auth_info.user_id));
+    for (field, outcome) in report.results.iter_mut().rev() {
+        if !matches!(outcome, Outcome::Applied) {
+            continue;
+        }
+        let result = match field {
+            Field::Image => match user.original_profile_image_url() {
+                Some(url) => match download(client, &url, image_dir.join("image")).await {
+                    Ok(path) => retry(retries, || client.update_profile_image(auth_info, &path))
+                        .await
+                        .map(|_| ()),
+                    Err(e) => Err(e),
+                },
+                // the default image is never set by the API
+                None => Ok(()),
+            },
+            Field::Banner => match user.profile_banner_url {
+                Some(ref url) => {
+                    let url = format!("{}/1500x500", url);
+                    match download(client, &url, image_dir.join("banner")).await {
+                        Ok(path) => {
+                            retry(retries, || client.update_profile_banner(auth_info, &path)).await
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+                None => retry(retries, || client.remove_profile_banner(auth_info)).await,
+            },
+            // the text is the last step, and the others are not changes
+            Field::Text | Field::Snapshot | Field::Intro => continue,
+        };
+        *outcome = match result {
+            Ok(_) => Outcome::RolledBack,
+            Err(e) => Outcome::RollbackFailed(e),
+        };
+    }
+}
+
+/// download the image of the snapshot, and returns the path of it.
+async fn download(
+    client: &dyn TwitterClient,
+    url: &str,
+    path_without_ext: PathBuf,
+) -> TwitterResult<String> {
+    if let Some(dir) = path_without_ext.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let path = client.download_image(url, &path_without_ext).await?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// run the request, and retry it on the transient failures.
+async fn retry<'a, T, F>(retries: u32, mut request: F) -> TwitterResult<T>
+where
+    F: FnMut() -> BoxFuture<'a, TwitterResult<T>>,
+{
+    let mut delay = RETRY_DELAY;
+    for _ in 0..retries {
+        match request().await {
+            Err(e) if e.is_transient() => {
+                println!("[WARN] Retrying in {} seconds: {}", delay.as_secs(), e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    request().await
+}
@@ -0,0 +1,178 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use crate::config::*;
+use crate::daemon::apply_profile;
+use crate::init;
+use crate::simulator;
+use crate::snapshot;
+use crate::state::{State, StateFile};
+use crate::twitter_api::client::TwitterClient;
+
+pub async fn init(base_url: &str, token_file: &str, config_file: &str) {
+    let api_key = init::load_or_init_api_key(base_url, token_file)
+        .await
+        .expect("failed to load the token file.");
+    init::load_or_init_config(base_url, &api_key, config_file)
+        .await
+        .expect("failed to load the configuration file.");
+    println!("{} and {} are ready.", token_file, config_file);
+}
+
+pub async fn validate(client: &dyn TwitterClient, config: Config) {
+    match config.validate(client).await {
+        Ok(config) => {
+            println!("The configuration is valid.");
+            for account in config.accounts() {
+                println!(
+                    "  {}: {} profile(s), {} schedule(s).",
+                    account.key,
+                    account.profiles().len(),
+                    account.schedules().len()
+                );
+            }
+        }
+        Err(e) => println!("[ERROR] {}", e),
+    }
+}
+
+pub async fn apply(
+    client: &dyn TwitterClient,
+    state_file_path: &str,
+    account: &Account,
+    mut state: StateFile,
+    key: &str,
+) {
+    let profile = match account.profiles().iter().find(|p| p.key == key) {
+        Some(profile) => profile,
+        None => {
+            println!("[ERROR] Profile \"{}\" is not found.", key);
+            return;
+        }
+    };
+    if apply_profile(
+        client,
+        account,
+        state.account_mut(&account.key),
+        profile,
+        None,
+    )
+    .await
+    {
+        state.account_mut(&account.key).touch();
+        state
+            .save_atomic(state_file_path)
+            .expect("failed to save the state file.");
+    }
+}
+
+pub fn list(account: &Account) {
+    for p in account.profiles() {
+        println!("{}", p.key);
+        if let Some(ref derive) = p.derive {
+            println!("  derive: {}", derive);
+        }
+        if !p.triggers.is_empty() {
+            println!("  triggers: {:?}", p.triggers);
+        }
+        if !p.matches.is_empty() {
+            println!("  matches: {:?}", p.matches);
+        }
+        if let (Some(ttl), Some(revert_to)) = (p.ttl, p.revert_to.as_ref()) {
+            println!("  revert to {} after {} sec.", revert_to, ttl);
+        }
+    }
+    for s in account.schedules() {
+        println!(
+            "schedule \"{}\" ({}) -> {}",
+            s.cron,
+            s.timezone.as_deref().unwrap_or("local"),
+            s.profile
+        );
+    }
+}
+
+pub fn dry_run(account: &Account, text: &str) {
+    simulator::simulate_text(account, text);
+}
+
+pub fn simulate(account: &Account, input_file: Option<&str>, source: Source) {
+    let result = match input_file {
+        Some(path) if path != "-" => {
+            let file = File::open(path).expect("failed to open the input file.");
+            simulator::simulate(account, BufReader::new(file), source)
+        }
+        _ => simulator::simulate(account, io::stdin(), source),
+    };
+    if let Err(e) = result {
+        println!("[ERROR] {}", e);
+    }
+}
+
+pub async fn take_snapshot<P: AsRef<Path>>(
+    client: &dyn TwitterClient,
+    snapshot_dir: P,
+    config: &Config,
+    account_key: &str,
+    state: &State,
+) {
+    let account = config
+        .account(account_key)
+        .expect("the account is not found.");
+    // the profile written by the user is not replaced
+    if account
+        .profiles()
+        .iter()
+        .any(|p| p.key == snapshot::UNMASK_PROFILE_KEY && !p.snapshot)
+    {
+        println!(
+            "[ERROR] Profile \"{}\" is in the configuration. Rename it to take the snapshot.",
+            snapshot::UNMASK_PROFILE_KEY
+        );
+        return;
+    }
+    if let Some(ref applied) = state.applied_profile {
+        println!(
+            "[WARN] Profile \"{}\" seems to be applied now. The snapshot records it as-is.",
+            applied
+        );
+    }
+    let profile = snapshot::take_snapshot(client, account, &snapshot_dir)
+        .await
+        .expect("failed to take the snapshot of the profile.");
+    println!("Snapshot taken: {:?}", profile);
+    let path = snapshot::save(&profile, &snapshot_dir).expect("failed to save the snapshot.");
+    println!(
+        "Profile \"{}\" has been written into {}.",
+        snapshot::UNMASK_PROFILE_KEY,
+        path.display()
+    );
+}
+
+pub async fn unmask(
+    client: &dyn TwitterClient,
+    state_file_path: &str,
+    account: &Account,
+    state: StateFile,
+) {
+    if !account
+        .profiles()
+        .iter()
+        .any(|p| p.key == snapshot::UNMASK_PROFILE_KEY)
+    {
+        println!(
+            "[ERROR] No snapshot found. Take the snapshot with \"twentyfaces snapshot\" first."
+        );
+        return;
+    }
+    println!("restoring the original profile...");
+    apply(
+        client,
+        state_file_path,
+        account,
+        state,
+        snapshot::UNMASK_PROFILE_KEY,
+    )
+    .await;
+}
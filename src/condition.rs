@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+use crate::twitter_api::models::Status;
+
+/// the condition on the tweet which the profile requires, in addition to the text.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// all of the conditions hold
+    All(Vec<Condition>),
+    /// any of the conditions holds
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+    /// the tweet has the hashtag, with or without "#"
+    Hashtag(String),
+    /// the tweet mentions the user, with or without "@"
+    Mention(String),
+    HasMedia(bool),
+    /// the tweet is posted by the client like "Twitter for iPhone"
+    Client(String),
+    /// whether the tweet is a reply. this overrides `trigger_reply` of the property.
+    Reply(bool),
+    /// whether the tweet is a quote. this overrides `trigger_quote` of the property.
+    Quote(bool),
+    /// whether the tweet is a retweet. this overrides `trigger_retweet` of the property.
+    Retweet(bool),
+}
+
+/// the attributes of the tweet which the conditions are evaluated on.
+#[derive(Debug, Default)]
+pub struct Features<'a> {
+    pub hashtags: Vec<&'a str>,
+    pub mentions: Vec<&'a str>,
+    pub has_media: bool,
+    pub client: Option<&'a str>,
+    pub reply: bool,
+    pub quote: bool,
+    pub retweet: bool,
+}
+
+impl<'a> Features<'a> {
+    pub fn of_status(status: &'a Status) -> Self {
+        Features {
+            hashtags: status.hashtags.iter().map(|s| s.as_str()).collect(),
+            mentions: status.mentions.iter().map(|s| s.as_str()).collect(),
+            has_media: status.has_media,
+            client: status.client.as_deref(),
+            reply: status.in_reply_to_status_id.is_some(),
+            quote: status.quoted_status.is_some(),
+            retweet: status.retweeted_status.is_some(),
+        }
+    }
+
+    /// the features of a plain tweet with the text, for the texts without the tweet data.
+    pub fn of_text(text: &'a str) -> Self {
+        let words = |prefix: char| {
+            text.split_whitespace()
+                .filter_map(move |w| w.strip_prefix(prefix))
+                .filter(|w| !w.is_empty())
+                .collect()
+        };
+        Features {
+            hashtags: words('#'),
+            mentions: words('@'),
+            ..Default::default()
+        }
+    }
+}
+
+impl Condition {
+    pub fn matches(&self, features: &Features) -> bool {
+        let contains = |items: &[&str], item: &str, prefix: char| {
+            let item = item.trim_start_matches(prefix);
+            items.iter().any(|i| i.eq_ignore_ascii_case(item))
+        };
+        match self {
+            Condition::All(conditions) => conditions.iter().all(|c| c.matches(features)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.matches(features)),
+            Condition::Not(condition) => !condition.matches(features),
+            Condition::Hashtag(tag) => contains(&features.hashtags, tag, '#'),
+            Condition::Mention(user) => contains(&features.mentions, user, '@'),
+            Condition::HasMedia(b) => features.has_media == *b,
+            Condition::Client(name) => features.client == Some(name.as_str()),
+            Condition::Reply(b) => features.reply == *b,
+            Condition::Quote(b) => features.quote == *b,
+            Condition::Retweet(b) => features.retweet == *b,
+        }
+    }
+
+    /// whether the condition includes the one satisfying the predicate.
+    pub fn refers<F: Fn(&Condition) -> bool + Copy>(&self, predicate: F) -> bool {
+        predicate(self)
+            || match self {
+                Condition::All(conditions) | Condition::Any(conditions) => {
+                    conditions.iter().any(|c| c.refers(predicate))
+                }
+                Condition::Not(condition) => condition.refers(predicate),
+                _ => false,
+            }
+    }
+}
@@ -1,16 +1,27 @@
 use crate::{
-    errors::ConfigurationError, errors::Error, twitter_api::account::update_profile,
-    twitter_api::account::update_profile_banner, twitter_api::account::update_profile_image,
-    twitter_api::misc::check_user_auth, twitter_api::TwitterResult,
+    condition::Condition,
+    errors::ConfigurationError,
+    errors::Error,
+    image_source::ImageSource,
+    imaging::{ImageKind, ImageProcessing},
+    normalize::Normalization,
+    snapshot,
+    template::{self, Variables},
+    twitter_api::client::TwitterClient,
+    twitter_api::TwitterDataError,
 };
 
 use super::twitter_api;
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone, Utc};
 use reqwest_oauth1::Secrets;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_yaml;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
 use std::io::BufReader;
 use std::io::BufWriter;
+use std::io::Write;
 use std::path::Path;
 use std::{collections::hash_map::Entry, io};
 use std::{collections::HashMap, fs::File};
@@ -36,6 +47,22 @@ pub trait SaveAndLoad: Sized + Serialize + DeserializeOwned {
         Ok(())
     }
 
+    /// save into the temporary file first, then replace the target file with it.
+    fn save_atomic<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let path = path.as_ref();
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        {
+            let file = File::create(&temp_path)?;
+            let mut writer = BufWriter::new(file);
+            serde_yaml::to_writer(&mut writer, self)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
     fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
@@ -65,11 +92,27 @@ impl ApiKey {
     }
 }
 
+/// the key of the account configured at the top level of the configuration.
+pub static DEFAULT_ACCOUNT_KEY: &str = "default";
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
     auth_info: AuthInfo,
     property: Property,
     profiles: Vec<Profile>,
+    #[serde(default)]
+    schedules: Vec<Schedule>,
+    /// the profiles shared by the accounts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    profile_sets: Vec<ProfileSet>,
+    /// the accounts other than the default one.
+    #[serde(rename = "accounts", default, skip_serializing_if = "Vec::is_empty")]
+    other_accounts: Vec<Account>,
+    #[serde(skip)]
+    account_instances: Vec<Account>,
+    /// the snapshot profiles keyed by the account, which are kept in their own files.
+    #[serde(skip)]
+    snapshots: HashMap<String, Profile>,
 }
 
 impl SaveAndLoad for Config {}
@@ -80,17 +123,198 @@ impl Config {
             auth_info,
             property,
             profiles: profiles.to_vec(),
+            schedules: Vec::new(),
+            profile_sets: Vec::new(),
+            other_accounts: Vec::new(),
+            account_instances: Vec::new(),
+            snapshots: HashMap::new(),
         }
     }
 
+    /// load the configuration with the snapshots taken for its accounts.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let mut config = Config::load(path.as_ref())?;
+        let keys = std::iter::once(DEFAULT_ACCOUNT_KEY)
+            .chain(config.other_accounts.iter().map(|a| a.key.as_str()))
+            .map(String::from)
+            .collect::<Vec<_>>();
+        for key in keys {
+            let dir = snapshot::snapshot_dir(path.as_ref(), &key);
+            if let Some(profile) = snapshot::load(dir)? {
+                config.snapshots.insert(key, profile);
+            }
+        }
+        Ok(config)
+    }
+
     pub fn new_example(auth_info: AuthInfo) -> Config {
-        Config {
+        Config::new(
             auth_info,
-            property: Property::create_sample(),
-            profiles: vec![Profile::create_sample()],
+            Property::create_sample(),
+            &[Profile::create_sample()],
+        )
+    }
+
+    /// the accounts including the default one, available after the validation.
+    pub fn accounts(&self) -> &[Account] {
+        &self.account_instances
+    }
+
+    pub fn account(&self, key: &str) -> Option<&Account> {
+        self.accounts().iter().find(|a| a.key == key)
+    }
+
+    pub async fn validate(self, client: &dyn TwitterClient) -> Result<Self, Error> {
+        let config = self.validate_offline()?;
+        // check authentication information, including the targets of the profiles
+        for account in config.accounts() {
+            client.check_user_auth(account.auth_info()).await?;
+        }
+        Ok(config)
+    }
+
+    /// validate the configuration without network access.
+    pub fn validate_offline(mut self) -> Result<Self, Error> {
+        // 1. check all of the accounts and the profile sets has each unique keys
+        let mut account_keys = vec![DEFAULT_ACCOUNT_KEY];
+        for account in self.other_accounts.iter() {
+            if account_keys.contains(&account.key.as_str()) {
+                return Err(ConfigurationError::new(format!(
+                    "Duplicated account key has been detected: {}",
+                    account.key
+                ))
+                .into());
+            }
+            account_keys.push(account.key.as_str());
+        }
+        let mut set_keys = Vec::new();
+        for set in self.profile_sets.iter() {
+            if set_keys.contains(&set.key.as_str()) {
+                return Err(ConfigurationError::new(format!(
+                    "Duplicated profile set key has been detected: {}",
+                    set.key
+                ))
+                .into());
+            }
+            set_keys.push(set.key.as_str());
         }
+
+        // 2. build the accounts with the shared profiles and the default property
+        let default = Account {
+            key: DEFAULT_ACCOUNT_KEY.to_string(),
+            auth_info: self.auth_info.clone(),
+            property: Some(self.property.clone()),
+            profiles: self.profiles.clone(),
+            profile_sets: Vec::new(),
+            schedules: self.schedules.clone(),
+        };
+        let mut accounts = vec![default];
+        for account in self.other_accounts.iter() {
+            let mut account = account.clone();
+            account
+                .property
+                .get_or_insert_with(|| self.property.clone());
+            for key in account.profile_sets.iter() {
+                let set = self
+                    .profile_sets
+                    .iter()
+                    .find(|s| s.key == *key)
+                    .ok_or_else(|| {
+                        ConfigurationError::new(format!(
+                            "Unknown profile set {} is specified in account {}",
+                            key, account.key
+                        ))
+                    })?;
+                account.profiles.extend(set.profiles.iter().cloned());
+            }
+            accounts.push(account);
+        }
+        // the profile written in the configuration takes precedence over the snapshot
+        for account in accounts.iter_mut() {
+            let snapshot = match self.snapshots.get(&account.key) {
+                Some(snapshot) => snapshot,
+                None => continue,
+            };
+            if account.profiles.iter().any(|p| p.key == snapshot.key) {
+                println!(
+                    "[WARN] Profile \"{}\" of account {} is in the configuration, and the snapshot is not used.",
+                    snapshot.key, account.key
+                );
+            } else {
+                account.profiles.push(snapshot.clone());
+            }
+        }
+
+        // 3. check each of the accounts
+        for account in accounts.iter_mut() {
+            let key = account.key.clone();
+            account
+                .validate()
+                .map_err(|e| e.context(format!("account {}", key)))?;
+        }
+
+        // 4. check the target accounts of the profiles are valid
+        let auth_infos = accounts
+            .iter()
+            .map(|a| (a.key.clone(), a.auth_info.clone()))
+            .collect::<HashMap<_, _>>();
+        for account in accounts.iter_mut() {
+            let key = account.key.clone();
+            for p in account.profiles.iter_mut() {
+                let target = match p.target {
+                    Some(ref target) if *target != key => target,
+                    _ => continue,
+                };
+                let auth_info = auth_infos.get(target).ok_or_else(|| {
+                    ConfigurationError::new(format!(
+                        "Unknown account {} is specified as target in profile {}",
+                        target, p.key
+                    ))
+                    .context(format!("account {}", key))
+                })?;
+                p.target_auth_info = Some(auth_info.clone());
+            }
+            for p in account.profiles() {
+                if p.target_auth_info.is_some() && p.resolve(account.profiles())?.ttl.is_some() {
+                    return Err(ConfigurationError::new(format!(
+                        "ttl is not supported in profile {} which targets another account",
+                        p.key
+                    ))
+                    .context(format!("account {}", key))
+                    .into());
+                }
+            }
+        }
+        self.account_instances = accounts;
+        Ok(self)
     }
+}
 
+/// the set of the profiles which can be shared by the accounts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProfileSet {
+    pub key: String,
+    pub profiles: Vec<Profile>,
+}
+
+/// the account which switches its profile. the default account is configured at the top level.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Account {
+    pub key: String,
+    auth_info: AuthInfo,
+    /// the property of the default account is used if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    property: Option<Property>,
+    #[serde(default)]
+    profiles: Vec<Profile>,
+    /// keys of the profile sets used in addition to `profiles`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    profile_sets: Vec<String>,
+    #[serde(default)]
+    schedules: Vec<Schedule>,
+}
+
+impl Account {
     pub fn auth_info(&self) -> &AuthInfo {
         &self.auth_info
     }
@@ -100,12 +324,37 @@ impl Config {
     }
 
     pub fn property(&self) -> &Property {
-        &self.property
+        self.property
+            .as_ref()
+            .expect("the property is filled on validation")
     }
 
-    pub async fn validate(mut self, api_key: &ApiKey) -> Result<Self, Error> {
-        // check authentication information
-        let _ = check_user_auth(api_key, self.auth_info()).await?;
+    pub fn schedules(&self) -> &[Schedule] {
+        &self.schedules
+    }
+
+    /// the timelines watched by the profiles of this account.
+    pub fn sources(&self) -> Vec<Source> {
+        [Source::Own, Source::Mentions, Source::Home]
+            .iter()
+            .copied()
+            .filter(|s| self.profiles.iter().any(|p| p.accepts(*s)))
+            .collect()
+    }
+
+    /// find the schedule fired at the latest in the range (from, to].
+    pub fn due_schedule(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Option<(&Schedule, DateTime<Utc>)> {
+        self.schedules()
+            .iter()
+            .filter_map(|s| s.last_fire_between(from, to).map(|t| (s, t)))
+            .max_by_key(|(_, t)| *t)
+    }
+
+    fn validate(&mut self) -> Result<(), ConfigurationError> {
         // check profiles
         let mut map = HashMap::new();
 
@@ -125,57 +374,84 @@ impl Config {
 
         // 2. check the derived profile keys are valid
         for mut profile in self.profiles() {
-            loop {
-                match &profile.derive {
-                    Some(p) => {
-                        // resolve profile
-                        profile = map.get(p.as_str()).ok_or_else(|| {
-                            ConfigurationError::new(format!(
-                                "Unknown profile key {} is specified as derive in profile {}",
-                                profile.key, p
-                            ))
-                        })?;
-                    }
-                    None => break,
+            while let Some(p) = &profile.derive {
+                // resolve profile
+                profile = map.get(p.as_str()).ok_or_else(|| {
+                    ConfigurationError::new(format!(
+                        "Unknown profile key {} is specified as derive in profile {}",
+                        p, profile.key
+                    ))
+                })?;
+            }
+        }
+
+        // 3. check the profile keys to revert are valid
+        for profile in self.profiles() {
+            if let Some(ref r) = profile.revert_to {
+                if !map.contains_key(r.as_str()) {
+                    return Err(ConfigurationError::new(format!(
+                        "Unknown profile key {} is specified as revert_to in profile {}",
+                        r, profile.key
+                    )));
                 }
             }
+            let resolved = profile.resolve(self.profiles())?;
+            if resolved.ttl.is_some() != resolved.revert_to.is_some() {
+                return Err(ConfigurationError::new(format!(
+                    "ttl and revert_to should be specified together in profile {}",
+                    profile.key
+                )));
+            }
         }
 
-        // 3. check the regex patterns in the profile is valid
-        for mut p in self.profiles.iter_mut() {
+        // 4. check the regex patterns in the profile is valid
+        for p in self.profiles.iter_mut() {
+            let case_insensitive = p.normalize.case_fold;
             p.match_instances = p
                 .matches
                 .iter()
-                .map(|p| regex::Regex::new(p))
+                .map(|p| {
+                    regex::RegexBuilder::new(p)
+                        .case_insensitive(case_insensitive)
+                        .build()
+                })
                 .collect::<Result<Vec<regex::Regex>, regex::Error>>()
                 .map_err(|e| ConfigurationError::new(format!("invalid regex pattern: {:?}", e)))?;
         }
 
-        // 4. check file existence
+        // 5. check the polling properties and the schedules are valid
+        if let Some(ref mut property) = self.property {
+            property.compile()?;
+        }
+        for s in self.schedules.iter_mut() {
+            s.compile()?;
+            if !self.profiles.iter().any(|p| p.key == s.profile) {
+                return Err(ConfigurationError::new(format!(
+                    "Unknown profile key {} is specified in schedule {}",
+                    s.profile, s.cron
+                )));
+            }
+        }
+
+        // 6. check the images are uploadable
+        let processing = self
+            .property
+            .as_ref()
+            .and_then(|p| p.image_processing.as_ref());
         for p in self.profiles.iter() {
-            // check profile image
             if let Some(ref image) = p.image {
-                if !Path::new(image).exists() {
-                    return Err(ConfigurationError::new(format!(
-                        "Specified profile image {} is not found in profile {}",
-                        image, p.key
-                    ))
-                    .into());
-                }
+                image
+                    .check(ImageKind::Avatar, processing)
+                    .map_err(|e| e.context(format!("profile {}", p.key)))?;
             }
-            // check banner image
-            if let Some(ref banner) = p.banner {
-                if !Path::new(banner).exists() {
-                    return Err(ConfigurationError::new(format!(
-                        "Specified profile banner {} is not found in profile {}",
-                        banner, p.key
-                    ))
-                    .into());
-                }
+            if let Some(banner) = p.banner.as_ref().and_then(Clearable::value) {
+                banner
+                    .check(ImageKind::Banner, processing)
+                    .map_err(|e| e.context(format!("profile {}", p.key)))?;
             }
         }
 
-        Ok(self)
+        Ok(())
     }
 }
 
@@ -189,7 +465,7 @@ pub struct AuthInfo {
 impl AuthInfo {
     pub fn new(user_id: u64, token: impl Into<String>, secret: impl Into<String>) -> AuthInfo {
         AuthInfo {
-            user_id: user_id,
+            user_id,
             token: token.into(),
             secret: secret.into(),
         }
@@ -216,11 +492,55 @@ impl<'a> AuthInfoConfigurer<'a> for Secrets<'a> {
     }
 }
 
-#[derive(Clone, Hash, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Property {
     pub trigger_retweet: bool,
     pub trigger_quote: bool,
     pub trigger_reply: bool,
+    /// seconds between polling the timeline.
+    #[serde(default = "default_receive_interval")]
+    pub receive_interval: u64,
+    /// the number of tweets fetched in a poll, up to 200.
+    #[serde(default = "default_fetch_count")]
+    pub fetch_count: u32,
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+    /// screen names of the users allowed to trigger the profiles by mentions.
+    #[serde(default)]
+    pub mention_users: Vec<String>,
+    /// IDs of the users allowed to send the commands by direct messages.
+    #[serde(default)]
+    pub dm_users: Vec<u64>,
+    /// IANA time zone name of `{date}` and `{time}` in the profile fields. the local time zone
+    /// is used if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(skip)]
+    pub timezone_instance: Option<chrono_tz::Tz>,
+    /// which profiles to apply when several tweets in a poll trigger them.
+    #[serde(default, skip_serializing_if = "BatchPolicy::is_default")]
+    pub batch_policy: BatchPolicy,
+    /// how many times a step of applying the profile is retried on the transient failures.
+    #[serde(default = "default_apply_retries")]
+    pub apply_retries: u32,
+    /// deleting the trigger tweets of all profiles, unless the profile specifies its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delete_trigger: Option<DeleteTrigger>,
+    /// process the images before uploading them. they are uploaded as they are if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_processing: Option<ImageProcessing>,
+}
+
+fn default_receive_interval() -> u64 {
+    10
+}
+
+fn default_fetch_count() -> u32 {
+    200
+}
+
+fn default_apply_retries() -> u32 {
+    2
 }
 
 impl Property {
@@ -229,6 +549,359 @@ impl Property {
             trigger_retweet: false,
             trigger_quote: false,
             trigger_reply: false,
+            receive_interval: default_receive_interval(),
+            fetch_count: default_fetch_count(),
+            quiet_hours: None,
+            timezone: None,
+            timezone_instance: None,
+            batch_policy: BatchPolicy::default(),
+            apply_retries: default_apply_retries(),
+            image_processing: None,
+            mention_users: Vec::new(),
+            dm_users: Vec::new(),
+            delete_trigger: None,
+        }
+    }
+
+    /// whether the mention of the user can trigger the profiles.
+    pub fn allows_mention_from(&self, screen_name: &str) -> bool {
+        self.mention_users
+            .iter()
+            .any(|u| u.trim_start_matches('@').eq_ignore_ascii_case(screen_name))
+    }
+
+    /// the interval of polling at the time, slowed down in the quiet hours.
+    pub fn receive_interval_at(&self, now: DateTime<Utc>) -> Duration {
+        let seconds = match self.quiet_hours {
+            Some(ref q) if q.contains(now) => q.interval,
+            _ => self.receive_interval,
+        };
+        Duration::seconds(seconds as i64)
+    }
+
+    /// format the time in the time zone of the property.
+    pub fn format_time(&self, time: DateTime<Utc>, format: &str) -> String {
+        match self.timezone_instance {
+            Some(ref tz) => time.with_timezone(tz).format(format).to_string(),
+            None => time.with_timezone(&Local).format(format).to_string(),
+        }
+    }
+
+    fn compile(&mut self) -> Result<(), ConfigurationError> {
+        if self.receive_interval == 0 {
+            return Err(ConfigurationError::new(
+                "receive_interval should be 1 second or longer",
+            ));
+        }
+        if !(1..=200).contains(&self.fetch_count) {
+            return Err(ConfigurationError::new(format!(
+                "fetch_count should be between 1 and 200: {}",
+                self.fetch_count
+            )));
+        }
+        if let Some(ref mut q) = self.quiet_hours {
+            q.compile()?;
+        }
+        self.timezone_instance = match self.timezone {
+            Some(ref tz) => Some(tz.parse().map_err(|e| {
+                ConfigurationError::new(format!("invalid time zone {}: {}", tz, e))
+            })?),
+            None => None,
+        };
+        Ok(())
+    }
+}
+
+/// which profiles to apply when several tweets in a poll trigger them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchPolicy {
+    /// the profile triggered by the newest tweet
+    #[default]
+    Newest,
+    /// the profile with the highest priority, or the newest one among them
+    Priority,
+    /// all profiles in the order of the tweets. the last one remains.
+    Chronological,
+}
+
+impl BatchPolicy {
+    fn is_default(&self) -> bool {
+        *self == BatchPolicy::default()
+    }
+}
+
+fn is_zero(n: &i32) -> bool {
+    *n == 0
+}
+
+/// deleting the own tweet which triggered the profile, after the profile is applied.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeleteTrigger {
+    /// false to keep the trigger tweets of the profile while deleting the others.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub on_failure: FailurePolicy,
+    /// delete the tweet even if a `matches` pattern matched only a part of the text.
+    #[serde(default)]
+    pub allow_partial: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// what to do when deleting the trigger tweet failed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePolicy {
+    /// give up silently
+    Ignore,
+    /// give up with a warning
+    #[default]
+    Warn,
+    /// retry at the following polls until it succeeds or fails permanently
+    Retry,
+}
+
+/// the time window polling with the slower interval, like "23:00" to "07:00".
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+    /// seconds between polling in the quiet hours.
+    pub interval: u64,
+    /// IANA time zone name like "Asia/Tokyo". the local time zone is used if not specified.
+    pub timezone: Option<String>,
+    #[serde(skip)]
+    pub start_instance: Option<NaiveTime>,
+    #[serde(skip)]
+    pub end_instance: Option<NaiveTime>,
+    #[serde(skip)]
+    pub timezone_instance: Option<chrono_tz::Tz>,
+}
+
+impl QuietHours {
+    fn compile(&mut self) -> Result<(), ConfigurationError> {
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M").map_err(|e| {
+                ConfigurationError::new(format!("invalid time of quiet_hours {}: {}", time, e))
+            })
+        };
+        self.start_instance = Some(parse(&self.start)?);
+        self.end_instance = Some(parse(&self.end)?);
+        if self.interval == 0 {
+            return Err(ConfigurationError::new(
+                "interval of quiet_hours should be 1 second or longer",
+            ));
+        }
+        self.timezone_instance = match self.timezone {
+            Some(ref tz) => Some(tz.parse().map_err(|e| {
+                ConfigurationError::new(format!("invalid time zone {}: {}", tz, e))
+            })?),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// whether the time is in [start, end), which may wrap around midnight.
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let (start, end) = match (self.start_instance, self.end_instance) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return false,
+        };
+        let time = match self.timezone_instance {
+            Some(ref tz) => now.with_timezone(tz).time(),
+            None => now.with_timezone(&Local).time(),
+        };
+        if start <= end {
+            start <= time && time < end
+        } else {
+            start <= time || time < end
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Schedule {
+    /// cron expression: "min hour day month weekday" (0 or 7 is Sunday), or with the leading
+    /// seconds field in the syntax of cron crate (1 is Sunday).
+    pub cron: String,
+    /// IANA time zone name like "Asia/Tokyo". the local time zone is used if not specified.
+    pub timezone: Option<String>,
+    pub profile: String,
+    #[serde(skip)]
+    pub cron_instance: Option<cron::Schedule>,
+    #[serde(skip)]
+    pub timezone_instance: Option<chrono_tz::Tz>,
+}
+
+impl Schedule {
+    fn compile(&mut self) -> Result<(), ConfigurationError> {
+        // cron crate requires the seconds field, and counts the weekdays from 1 = Sunday
+        let fields: Vec<&str> = self.cron.split_whitespace().collect();
+        let expression = if fields.len() == 5 {
+            let weekdays = weekday_names(fields[4]).map_err(|e| {
+                ConfigurationError::new(format!("invalid cron expression {}: {}", self.cron, e))
+            })?;
+            format!("0 {} {}", fields[..4].join(" "), weekdays)
+        } else {
+            self.cron.clone()
+        };
+        self.cron_instance = Some(expression.parse().map_err(|e| {
+            ConfigurationError::new(format!("invalid cron expression {}: {}", self.cron, e))
+        })?);
+        self.timezone_instance = match self.timezone {
+            Some(ref tz) => Some(tz.parse().map_err(|e| {
+                ConfigurationError::new(format!("invalid time zone {}: {}", tz, e))
+            })?),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// the last time this schedule fires in the range (from, to].
+    pub fn last_fire_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let cron = self.cron_instance.as_ref()?;
+        match self.timezone_instance {
+            Some(ref tz) => last_fire_between(cron, tz, from, to),
+            None => last_fire_between(cron, &Local, from, to),
+        }
+    }
+}
+
+/// the weekdays of the standard cron, where both 0 and 7 are Sunday.
+const WEEKDAY_NAMES: [&str; 8] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT", "SUN"];
+
+/// turn the numeric weekdays of the standard cron into the names, which cron crate reads
+/// the same way. the names and `*` are left as they are.
+fn weekday_names(field: &str) -> Result<String, String> {
+    let name = |day: &str| match day.parse::<usize>() {
+        Ok(n) => WEEKDAY_NAMES
+            .get(n)
+            .map(|name| name.to_string())
+            .ok_or_else(|| format!("weekday {} is out of 0-7", n)),
+        Err(_) => Ok(day.to_string()),
+    };
+    field
+        .split(',')
+        .map(|item| {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (item, None),
+            };
+            let range = match (range.split_once('-'), step) {
+                // SUN-SUN would be empty
+                (Some((start, "7")), None) => format!("{}-SAT,SUN", name(start)?),
+                (Some((_, "7")), Some(_)) => {
+                    return Err("use 0 for Sunday in a range with a step".to_string())
+                }
+                (Some((start, end)), _) => format!("{}-{}", name(start)?, name(end)?),
+                (None, _) => name(range)?,
+            };
+            Ok(match step {
+                Some(step) => format!("{}/{}", range, step),
+                None => range,
+            })
+        })
+        .collect::<Result<Vec<String>, String>>()
+        .map(|items| items.join(","))
+}
+
+fn last_fire_between<Z: TimeZone>(
+    cron: &cron::Schedule,
+    tz: &Z,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    cron.after(&from.with_timezone(tz))
+        .map(|t| t.with_timezone(&Utc))
+        .take_while(|t| *t <= to)
+        .last()
+}
+
+/// the timeline to find the triggers.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// the tweets of the account
+    Own,
+    /// the mentions to the account from the users in `mention_users`
+    Mentions,
+    /// the tweets of the followed users in the home timeline
+    Home,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Own => write!(f, "own"),
+            Source::Mentions => write!(f, "mentions"),
+            Source::Home => write!(f, "home"),
+        }
+    }
+}
+
+/// the value of the profile field which can be removed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    try_from = "ClearableRepr<T>",
+    into = "ClearableRepr<T>",
+    bound(
+        serialize = "T: Serialize + Clone",
+        deserialize = "T: Deserialize<'de>"
+    )
+)]
+pub enum Clearable<T = String> {
+    Set(T),
+    Clear,
+}
+
+impl<T> Clearable<T> {
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Clearable::Set(value) => Some(value),
+            Clearable::Clear => None,
+        }
+    }
+}
+
+impl From<&str> for Clearable {
+    fn from(value: &str) -> Self {
+        Clearable::Set(value.to_string())
+    }
+}
+
+/// a string, or `false` to clear.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ClearableRepr<T> {
+    Set(T),
+    Clear(bool),
+}
+
+impl<T> TryFrom<ClearableRepr<T>> for Clearable<T> {
+    type Error = String;
+
+    fn try_from(repr: ClearableRepr<T>) -> Result<Self, Self::Error> {
+        match repr {
+            ClearableRepr::Set(value) => Ok(Clearable::Set(value)),
+            ClearableRepr::Clear(false) => Ok(Clearable::Clear),
+            ClearableRepr::Clear(true) => Err("specify a value, or false to clear".to_string()),
+        }
+    }
+}
+
+impl<T> From<Clearable<T>> for ClearableRepr<T> {
+    fn from(value: Clearable<T>) -> Self {
+        match value {
+            Clearable::Set(value) => ClearableRepr::Set(value),
+            Clearable::Clear => ClearableRepr::Clear(false),
         }
     }
 }
@@ -236,30 +909,70 @@ impl Property {
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Profile {
     pub key: String,
+    #[serde(default)]
     pub matches: Vec<String>,
+    #[serde(default)]
     pub triggers: Vec<String>,
+    /// applied to the tweet text and `triggers` before matching.
+    /// this is not inherited by `derive` as well as the triggers.
+    #[serde(default, skip_serializing_if = "Normalization::is_none")]
+    pub normalize: Normalization,
+    /// the profile with the higher priority wins when a tweet triggers several profiles.
+    /// this is not inherited by `derive` as well as the triggers.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
+    /// required in addition to `triggers` or `matches`, or alone if both are empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+    /// the timelines to find the triggers. own tweets only if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<Source>,
     pub derive: Option<String>,
     pub name: Option<String>,
-    pub url: Option<String>,
-    pub location: Option<String>,
-    pub description: Option<String>,
-    pub image: Option<String>,
-    pub banner: Option<String>,
+    /// `false` clears the field. `url`, `location`, `description` and `banner` are inherited
+    /// or kept if not specified.
+    pub url: Option<Clearable>,
+    pub location: Option<Clearable>,
+    pub description: Option<Clearable>,
+    /// Twitter API has no way to reset the icon to the default, so this cannot be cleared.
+    pub image: Option<ImageSource>,
+    pub banner: Option<Clearable<ImageSource>>,
     pub intro: Option<String>,
+    /// seconds to keep this profile before reverting to `revert_to`
+    pub ttl: Option<u64>,
+    pub revert_to: Option<String>,
+    /// the key of the account to apply this profile instead of the triggered one.
+    /// this is not inherited by `derive`.
+    pub target: Option<String>,
+    /// overrides `delete_trigger` of the property.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delete_trigger: Option<DeleteTrigger>,
     #[serde(skip)]
     pub match_instances: Vec<regex::Regex>,
+    #[serde(skip)]
+    pub target_auth_info: Option<AuthInfo>,
+    /// whether this is loaded from the snapshot, not from the configuration.
+    #[serde(skip)]
+    pub snapshot: bool,
 }
 
+impl SaveAndLoad for Profile {}
+
 #[derive(Clone, Debug, Default)]
 pub struct ResolvedProfile<'a> {
-    pub derive: Option<&'a String>,
+    pub key: &'a str,
     pub name: Option<&'a String>,
-    pub url: Option<&'a String>,
-    pub location: Option<&'a String>,
-    pub description: Option<&'a String>,
-    pub image: Option<&'a String>,
-    pub banner: Option<&'a String>,
+    pub url: Option<&'a Clearable>,
+    pub location: Option<&'a Clearable>,
+    pub description: Option<&'a Clearable>,
+    pub image: Option<&'a ImageSource>,
+    pub banner: Option<&'a Clearable<ImageSource>>,
     pub intro: Option<&'a String>,
+    pub ttl: Option<u64>,
+    pub revert_to: Option<&'a String>,
+    pub delete_trigger: Option<&'a DeleteTrigger>,
+    /// the account to apply the profile, if it is not the triggered one.
+    pub target_auth_info: Option<&'a AuthInfo>,
 }
 
 impl Profile {
@@ -268,15 +981,34 @@ impl Profile {
             key: "sample".to_string(),
             matches: vec![".*change.*sample".to_string()],
             triggers: vec!["sample".to_string()],
+            normalize: Normalization::default(),
+            priority: 0,
+            condition: None,
+            sources: Vec::new(),
             derive: None,
             name: Some("sample profile".to_string()),
-            url: Some("example.com".to_string()),
-            location: Some("sample location".to_string()),
-            description: Some("this is sample profile.".to_string()),
+            url: Some("example.com".into()),
+            location: Some("sample location".into()),
+            description: Some("this is sample profile.".into()),
             image: None,
             banner: None,
             intro: Some("Hello, I'm a example profile!".to_string()),
+            ttl: None,
+            revert_to: None,
+            target: None,
+            delete_trigger: None,
             match_instances: vec![regex::Regex::new(".*change.*sample").unwrap()],
+            target_auth_info: None,
+            snapshot: false,
+        }
+    }
+
+    /// whether the tweets from the source can trigger this profile.
+    pub fn accepts(&self, source: Source) -> bool {
+        if self.sources.is_empty() {
+            source == Source::Own
+        } else {
+            self.sources.contains(&source)
         }
     }
 
@@ -285,11 +1017,16 @@ impl Profile {
         profiles: &'a [Profile],
     ) -> Result<ResolvedProfile<'a>, ConfigurationError> {
         // resolve derived profile
-        let mut resolved: ResolvedProfile = Default::default();
+        let profile = profiles.iter().find(|p| p.key == self.key);
+        let mut resolved = ResolvedProfile {
+            key: profile.map_or("", |p| p.key.as_str()),
+            target_auth_info: profile.and_then(|p| p.target_auth_info.as_ref()),
+            ..Default::default()
+        };
         let mut key = Some(self.key.as_str());
         loop {
             // find deriving profile
-            let derived = key.and_then(|k| profiles.iter().filter(|p| p.key == k).next());
+            let derived = key.and_then(|k| profiles.iter().find(|p| p.key == k));
             if let Some(Profile {
                 name,
                 url,
@@ -298,6 +1035,9 @@ impl Profile {
                 image,
                 banner,
                 intro,
+                ttl,
+                revert_to,
+                delete_trigger,
                 derive,
                 ..
             }) = derived
@@ -311,6 +1051,9 @@ impl Profile {
                 resolved.image = resolved.image.or(image.as_ref());
                 resolved.banner = resolved.banner.or(banner.as_ref());
                 resolved.intro = resolved.intro.or(intro.as_ref());
+                resolved.ttl = resolved.ttl.or(*ttl);
+                resolved.revert_to = resolved.revert_to.or(revert_to.as_ref());
+                resolved.delete_trigger = resolved.delete_trigger.or(delete_trigger.as_ref());
                 // scan next derived key
                 key = derive.as_ref().map(|s| s.as_str());
             } else {
@@ -321,26 +1064,56 @@ impl Profile {
     }
 }
 
-impl ResolvedProfile<'_> {
-    pub async fn apply(&self, api_key: &ApiKey, config: &Config) -> TwitterResult<()> {
-        let auth_info = config.auth_info();
-        if let Some(image_path) = self.image {
-            update_profile_image(api_key, auth_info, image_path.as_ref()).await?;
-        }
+/// the text fields of the profile with the placeholders filled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderedFields {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    pub intro: Option<String>,
+}
 
-        if let Some(image_path) = self.banner {
-            update_profile_banner(api_key, auth_info, image_path.as_ref()).await?
-        }
+/// the maximum length of the fields accepted by Twitter.
+const MAX_NAME_LENGTH: usize = 50;
+const MAX_URL_LENGTH: usize = 100;
+const MAX_LOCATION_LENGTH: usize = 30;
+const MAX_DESCRIPTION_LENGTH: usize = 160;
+/// Twitter counts some characters (like CJK) as two, which is not considered here.
+const MAX_TWEET_LENGTH: usize = 280;
 
-        update_profile(
-            api_key,
-            auth_info,
-            self.name.map(|s| s.as_str()),
-            self.url.map(|s| s.as_str()),
-            self.location.map(|s| s.as_str()),
-            self.description.map(|s| s.as_str()),
-        )
-        .await?;
-        Ok(())
+impl ResolvedProfile<'_> {
+    /// fill the placeholders with the variables, and check the lengths of the results.
+    pub fn render(&self, vars: &Variables) -> Result<RenderedFields, TwitterDataError> {
+        let render = |field: &str, value: Option<&String>, limit: usize| {
+            let value = match value {
+                Some(value) => template::render(value, vars),
+                None => return Ok(None),
+            };
+            let length = value.chars().count();
+            if length > limit {
+                return Err(TwitterDataError::new(
+                    field,
+                    format!(
+                        "{} characters exceed the limit of {}: {}",
+                        length, limit, value
+                    ),
+                ));
+            }
+            Ok(Some(value))
+        };
+        // the cleared field is sent as empty
+        let render_clearable = |field: &str, value: Option<&Clearable>, limit: usize| match value {
+            Some(Clearable::Set(value)) => render(field, Some(value), limit),
+            Some(Clearable::Clear) => Ok(Some(String::new())),
+            None => Ok(None),
+        };
+        Ok(RenderedFields {
+            name: render("name", self.name, MAX_NAME_LENGTH)?,
+            url: render_clearable("url", self.url, MAX_URL_LENGTH)?,
+            location: render_clearable("location", self.location, MAX_LOCATION_LENGTH)?,
+            description: render_clearable("description", self.description, MAX_DESCRIPTION_LENGTH)?,
+            intro: render("intro", self.intro, MAX_TWEET_LENGTH)?,
+        })
     }
 }
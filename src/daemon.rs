@@ -0,0 +1,711 @@
+use chrono::{DateTime, Local, Utc};
+use futures::future::join_all;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::{sync::mpsc::channel, sync::mpsc::Receiver, time::Duration};
+
+use crate::config::*;
+use crate::errors::{ConfigurationError, Error};
+use crate::pacer::Pacer;
+use crate::remote;
+use crate::state::{PendingRevert, ProfileStats, State, StateFile, NOTHING_RECEIVED};
+use crate::template::Variables;
+use crate::trigger::{check_triggered_profile, find_triggered_profiles, TriggerMatch};
+use crate::twitter_api::models::{
+    Status, Timeline, DIRECT_MESSAGES_RESOURCE, HOME_TIMELINE_RESOURCE, MENTIONS_TIMELINE_RESOURCE,
+    USER_TIMELINE_RESOURCE,
+};
+use crate::twitter_api::{client::TwitterClient, TwitterError, TwitterResult};
+
+pub async fn main_proc(
+    client: &dyn TwitterClient,
+    conf_file_path: &str,
+    state_file_path: &str,
+    mut config: Config,
+    mut state: StateFile,
+) {
+    // activate config file watcher
+    let (tx, rx) = channel();
+    let mut watcher: RecommendedWatcher = Watcher::new(tx, Duration::from_millis(500)).unwrap();
+    watcher
+        .watch(conf_file_path, RecursiveMode::Recursive)
+        .unwrap();
+    println!("Press CTRL+C to exit...");
+    let mut pacers: HashMap<String, Pacer> = HashMap::new();
+    for account in config.accounts() {
+        if let Some(last_status_id) = state
+            .account_mut(&account.key)
+            .last_status_id
+            .filter(|id| *id != NOTHING_RECEIVED)
+        {
+            println!(
+                "{}: Resuming from the status ID {}.",
+                account.key, last_status_id
+            );
+        }
+        let pacer = pacers.entry(account.key.clone()).or_default();
+        // start with the rate limit consumed by the previous run
+        match client.rate_limit_status(account.auth_info()).await {
+            Ok(status) => {
+                let mut resources: Vec<&str> =
+                    account.sources().into_iter().map(resource).collect();
+                if !account.property().dm_users.is_empty() {
+                    resources.push(DIRECT_MESSAGES_RESOURCE);
+                }
+                for resource in resources {
+                    if let Some(limit) = status.resources.get(resource) {
+                        println!(
+                            "{}: Rate limit of {}: {}/{} remaining, reset at {}.",
+                            account.key,
+                            resource,
+                            limit.remaining,
+                            limit.limit,
+                            limit.reset.with_timezone(&Local)
+                        );
+                        pacer.set_rate_limit(resource, limit.clone());
+                    }
+                }
+            }
+            Err(e) => println!(
+                "[WARN] {}: Retrieving the rate limit status: {}",
+                account.key, e
+            ),
+        }
+    }
+    loop {
+        if poll_accounts(client, &config, &mut state, &mut pacers, Utc::now()).await {
+            if let Err(e) = state.save_atomic(state_file_path) {
+                println!("[ERROR] Saving the state file: {}", e);
+            }
+        }
+        let mut next_recv = next_poll(&config, &pacers);
+        // spin wait
+        loop {
+            // check configuration changes and read it
+            if let Some(new_config) =
+                spin_until_update(&rx, conf_file_path, next_recv - Utc::now()).await
+            {
+                println!(
+                    "Configuration file changed and reloaded at {}",
+                    Local::now()
+                );
+                // update config if it is valid
+                config = match check_config(client, new_config).await {
+                    Ok(c) => c,
+                    Err(e) => {
+                        println!("[ERROR] The new token configured in the file seems be invalid.");
+                        println!("        {:?}", e);
+                        println!("        New configuration is not applied.");
+                        config
+                    }
+                };
+                // follow the new polling interval
+                next_recv = next_poll(&config, &pacers);
+            }
+            // wait until we have to acquire new timeline information
+            if Utc::now() > next_recv {
+                break;
+            }
+        }
+    }
+}
+
+/// poll the due accounts concurrently. each of them fails independently.
+/// returns true if the state of any account has changed.
+pub async fn poll_accounts(
+    client: &dyn TwitterClient,
+    config: &Config,
+    state: &mut StateFile,
+    pacers: &mut HashMap<String, Pacer>,
+    now: DateTime<Utc>,
+) -> bool {
+    // prepare the accounts added by reloading
+    for account in config.accounts() {
+        state.account_mut(&account.key);
+        pacers.entry(account.key.clone()).or_default();
+    }
+    let mut pacer_of: HashMap<&str, &mut Pacer> =
+        pacers.iter_mut().map(|(k, p)| (k.as_str(), p)).collect();
+    let polls = state.iter_mut().filter_map(|(key, state)| {
+        let account = config.account(key)?;
+        if account.profiles().is_empty() {
+            // only a target of the other accounts
+            return None;
+        }
+        let pacer = pacer_of.remove(key)?;
+        if !pacer.is_due(account.property(), now) {
+            return None;
+        }
+        Some(async move {
+            pacer.start(now);
+            let prev_state = state.clone();
+            process(client, account, state, pacer).await;
+            let receive_interval = account.property().receive_interval_at(now);
+            let interval = pacer.next_interval(receive_interval, now);
+            if interval > receive_interval {
+                println!(
+                    "{}: Polling is slowed down, the next poll is in {} seconds.",
+                    account.key,
+                    interval.num_seconds()
+                );
+            }
+            if state.differs_from(&prev_state) {
+                state.touch();
+                true
+            } else {
+                false
+            }
+        })
+    });
+    join_all(polls).await.contains(&true)
+}
+
+/// the earliest time to poll one of the accounts.
+fn next_poll(config: &Config, pacers: &HashMap<String, Pacer>) -> DateTime<Utc> {
+    config
+        .accounts()
+        .iter()
+        .map(|a| {
+            pacers
+                .get(&a.key)
+                .and_then(|p| p.next_poll(a.property()))
+                .unwrap_or_else(Utc::now)
+        })
+        .min()
+        .unwrap_or_else(Utc::now)
+}
+
+/// run a polling cycle: check the tweets, the schedules and the expiration of the profile.
+pub async fn process(
+    client: &dyn TwitterClient,
+    account: &Account,
+    state: &mut State,
+    pacer: &mut Pacer,
+) {
+    // only one of them is applied in a cycle: direct message commands take precedence over
+    // tweet triggers, tweet triggers over schedules, and schedules over expiration.
+    retry_deletes(client, account, state).await;
+    let commanded = remote::recv_and_run_commands(client, account, state, pacer).await;
+    // nothing is applied automatically while paused
+    let skip = commanded || state.paused;
+    let triggered = recv_and_fire_trigger(client, account, state, pacer, skip).await;
+    let scheduled = fire_schedule(client, account, state, skip || triggered).await;
+    if !skip && !triggered && !scheduled {
+        revert_if_expired(client, account, state).await;
+    }
+}
+
+pub async fn check_config(client: &dyn TwitterClient, config: Config) -> Result<Config, Error> {
+    // check configuration validity
+    let config = config.validate_offline()?;
+    for account in config.accounts() {
+        client.check_user_auth(account.auth_info()).await.map_err(|e| ConfigurationError::new(
+            format!("Configuration error: authorization token of account {} has been invalidated or expired.\ndetail: {}", account.key, e)
+        ))?;
+    }
+    Ok(config)
+}
+
+async fn spin_until_update(
+    rx: &Receiver<DebouncedEvent>,
+    conf_file_path: &str,
+    timeout: chrono::Duration,
+) -> Option<Config> {
+    let deadline = Utc::now() + timeout;
+    loop {
+        // check configuration has been changed
+        if let Some(c) = check_config_update(rx, conf_file_path) {
+            return Some(c);
+        }
+        // check
+        if Utc::now() > deadline {
+            return None;
+        }
+        // 0.1 msec await
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+fn check_config_update(rx: &Receiver<DebouncedEvent>, conf_file_path: &str) -> Option<Config> {
+    match rx.try_recv() {
+        Ok(event) => {
+            match event {
+                // fatal errors
+                notify::DebouncedEvent::NoticeRemove(_)
+                | notify::DebouncedEvent::Remove(_)
+                | notify::DebouncedEvent::Rename(_, _) => {
+                    panic!("Configuration file had been removed and that is unexpected behavior.")
+                }
+                notify::DebouncedEvent::Error(e, p) => {
+                    panic!("Configuration watcher failed: {:?} - {:?}", e, p);
+                }
+                _ => {
+                    // maybe the file has been changed
+                    Some(
+                        Config::open(conf_file_path)
+                            .expect("Failed to read the configuration file"),
+                    )
+                }
+            }
+        }
+        Err(err) => match err {
+            std::sync::mpsc::TryRecvError::Empty => {
+                // nothing to do
+                None
+            }
+            std::sync::mpsc::TryRecvError::Disconnected => {
+                // watcher failed
+                panic!("Configuration watcher has been exited unexpectedly.")
+            }
+        },
+    }
+}
+
+/// returns true when a profile is triggered. the triggered profile is dropped if `skip` is true.
+async fn recv_and_fire_trigger(
+    client: &dyn TwitterClient,
+    account: &Account,
+    state: &mut State,
+    pacer: &mut Pacer,
+    skip: bool,
+) -> bool {
+    // collect the new statuses of the sources
+    let mut statuses = Vec::new();
+    for source in account.sources() {
+        let last_received = *state.last_received(source);
+        let since_id = last_received.filter(|id| *id != NOTHING_RECEIVED);
+        match fetch_timeline(client, account, source, since_id).await {
+            Ok(timeline) => {
+                pacer.on_success(resource(source), timeline.rate_limit);
+                // pick max id
+                let max_id = timeline.statuses.iter().map(|s| s.id).max();
+                let mut received = timeline
+                    .statuses
+                    .into_iter()
+                    .filter(|s| last_received.map(|l| s.id > l).unwrap_or(true))
+                    .map(|s| (source, s));
+                if last_received.is_none() {
+                    if received
+                        .any(|(source, s)| check_triggered_profile(&s, source, account).is_some())
+                    {
+                        println!(
+                            "{}: the {} timeline is received at the first time, so treat as dry-run mode and not triggered.",
+                            account.key, source
+                        );
+                    }
+                } else {
+                    statuses.extend(received);
+                }
+                // remember the newest status we have seen. an empty timeline is received as
+                // well, not to drop the first trigger after it.
+                *state.last_received(source) = max_id.or(last_received).or(Some(NOTHING_RECEIVED));
+            }
+            Err(e) => {
+                println!(
+                    "[ERROR] {}: Retriving the {} timeline: {}",
+                    account.key, source, e
+                );
+                pacer.on_error(resource(source), &e);
+            }
+        }
+    }
+
+    // newest first
+    statuses.sort_by_key(|(_, s)| std::cmp::Reverse(s.id));
+    let mut candidates = Vec::new();
+    for (source, status) in statuses {
+        let mut matches = find_triggered_profiles(&status, source, account).into_iter();
+        // the highest priority in the tweet, and the others for logging
+        let trigger = match matches.next() {
+            Some(t) => t,
+            None => continue,
+        };
+        println!(
+            "{}: Candidate \"{}\" (priority {}) by {} in status {} of @{}: {}",
+            account.key,
+            trigger.profile.key,
+            trigger.profile.priority,
+            trigger.rule,
+            status.id,
+            status.user.screen_name,
+            status.text
+        );
+        for other in matches {
+            println!(
+                "{}:   also matched \"{}\" (priority {}) by {}",
+                account.key, other.profile.key, other.profile.priority, other.rule
+            );
+        }
+        candidates.push((status, trigger));
+    }
+    let chosen: Vec<(Status, TriggerMatch)> = match account.property().batch_policy {
+        BatchPolicy::Newest => candidates.into_iter().take(1).collect(),
+        BatchPolicy::Priority => {
+            // max_by_key returns the last one of the maximums, which is the newest when reversed
+            let highest = candidates
+                .into_iter()
+                .rev()
+                .max_by_key(|(_, t)| t.profile.priority);
+            highest.into_iter().collect()
+        }
+        BatchPolicy::Chronological => candidates.into_iter().rev().collect(),
+    };
+    if chosen.is_empty() {
+        return false;
+    }
+    if skip {
+        println!(
+            "{}: The trigger is ignored because the account is paused or commanded.",
+            account.key
+        );
+        return false;
+    }
+    for (status, trigger) in chosen {
+        println!(
+            "{}: Profile \"{}\" triggered by status of @{}: {}",
+            account.key, trigger.profile.key, status.user.screen_name, status.text
+        );
+        if apply_profile(
+            client,
+            account,
+            state,
+            trigger.profile,
+            Some((&status, &trigger)),
+        )
+        .await
+        {
+            delete_trigger(client, account, state, &status, &trigger).await;
+        }
+    }
+    true
+}
+
+/// delete the own tweet which triggered the applied profile, if configured.
+async fn delete_trigger(
+    client: &dyn TwitterClient,
+    account: &Account,
+    state: &mut State,
+    status: &Status,
+    trigger: &TriggerMatch<'_>,
+) {
+    let resolved = match trigger.profile.resolve(account.profiles()) {
+        Ok(r) => r,
+        Err(_) => return,
+    };
+    let config = match resolved
+        .delete_trigger
+        .or_else(|| account.property().delete_trigger.as_ref())
+    {
+        Some(c) if c.enabled => c,
+        _ => return,
+    };
+    if status.user.id != account.auth_info().user_id {
+        // the tweets of the others cannot be deleted
+        return;
+    }
+    if !config.allow_partial && !trigger.rule.matches_whole(&status.text) {
+        println!(
+            "{}: The trigger tweet is kept because {} matched only a part of it.",
+            account.key, trigger.rule
+        );
+        return;
+    }
+    match client.destroy_status(account.auth_info(), status.id).await {
+        Ok(_) => println!(
+            "{}: The trigger tweet {} is deleted.",
+            account.key, status.id
+        ),
+        Err(e) => match config.on_failure {
+            FailurePolicy::Ignore => {}
+            FailurePolicy::Warn => println!(
+                "[WARN] {}: Deleting the trigger tweet {}: {}",
+                account.key, status.id, e
+            ),
+            FailurePolicy::Retry => {
+                if give_up_deleting(account, status.id, &e) {
+                    return;
+                }
+                println!(
+                    "[WARN] {}: Deleting the trigger tweet {} failed, retrying later: {}",
+                    account.key, status.id, e
+                );
+                state.pending_deletes.push(status.id);
+            }
+        },
+    }
+}
+
+/// retry deleting the trigger tweets which failed before.
+async fn retry_deletes(client: &dyn TwitterClient, account: &Account, state: &mut State) {
+    let mut remaining = Vec::new();
+    for status_id in std::mem::take(&mut state.pending_deletes) {
+        match client.destroy_status(account.auth_info(), status_id).await {
+            Ok(_) => println!(
+                "{}: The trigger tweet {} is deleted.",
+                account.key, status_id
+            ),
+            Err(e) => {
+                if !give_up_deleting(account, status_id, &e) {
+                    remaining.push(status_id);
+                }
+            }
+        }
+    }
+    state.pending_deletes = remaining;
+}
+
+/// returns true if the deletion never succeeds, like the tweet is already gone.
+fn give_up_deleting(account: &Account, status_id: u64, error: &TwitterError) -> bool {
+    if error.is_transient() {
+        return false;
+    }
+    println!(
+        "[WARN] {}: Deleting the trigger tweet {} is given up: {}",
+        account.key, status_id, error
+    );
+    true
+}
+
+async fn fetch_timeline(
+    client: &dyn TwitterClient,
+    account: &Account,
+    source: Source,
+    since_id: Option<u64>,
+) -> TwitterResult<Timeline> {
+    let user = account.auth_info();
+    let count = Some(account.property().fetch_count);
+    match source {
+        Source::Own => client.user_timeline(user, count, since_id).await,
+        Source::Mentions => client.mentions_timeline(user, count, since_id).await,
+        Source::Home => client.home_timeline(user, count, since_id).await,
+    }
+}
+
+/// the resource name of the source in the rate limit status.
+fn resource(source: Source) -> &'static str {
+    match source {
+        Source::Own => USER_TIMELINE_RESOURCE,
+        Source::Mentions => MENTIONS_TIMELINE_RESOURCE,
+        Source::Home => HOME_TIMELINE_RESOURCE,
+    }
+}
+
+/// apply the profile of the schedule fired since the last check. returns true when fired.
+/// the fired schedule is dropped if `skip` is true.
+async fn fire_schedule(
+    client: &dyn TwitterClient,
+    account: &Account,
+    state: &mut State,
+    skip: bool,
+) -> bool {
+    let now = Utc::now();
+    let due = state
+        .last_schedule_check
+        .and_then(|last_check| account.due_schedule(last_check, now));
+    let (schedule, fired_at) = match due {
+        Some(due) => due,
+        None => {
+            state.last_schedule_check = Some(now);
+            return false;
+        }
+    };
+    if skip {
+        println!(
+            "{}: Schedule of \"{}\" at {} is skipped because another profile is applied or the account is paused.",
+            account.key,
+            schedule.profile,
+            fired_at.with_timezone(&Local)
+        );
+        state.last_schedule_check = Some(now);
+        return false;
+    }
+    println!(
+        "{}: Profile \"{}\" is scheduled at {}.",
+        account.key,
+        schedule.profile,
+        fired_at.with_timezone(&Local)
+    );
+    match account
+        .profiles()
+        .iter()
+        .find(|p| p.key == schedule.profile)
+    {
+        Some(profile) => {
+            // keep the last check time on failure to retry it at the next time.
+            if apply_profile(client, account, state, profile, None).await {
+                state.last_schedule_check = Some(now);
+            }
+        }
+        None => {
+            println!(
+                "[ERROR] {}: Profile \"{}\" is not found, the schedule is skipped.",
+                account.key, schedule.profile
+            );
+            state.last_schedule_check = Some(now);
+        }
+    }
+    true
+}
+
+async fn revert_if_expired(client: &dyn TwitterClient, account: &Account, state: &mut State) {
+    let pending = match state.pending_revert {
+        Some(ref pending) if pending.at <= Utc::now() => pending.clone(),
+        _ => return,
+    };
+    println!(
+        "{}: Profile \"{}\" has been expired, reverting to \"{}\".",
+        account.key,
+        state.applied_profile.as_deref().unwrap_or_default(),
+        pending.profile
+    );
+    match account.profiles().iter().find(|p| p.key == pending.profile) {
+        // keep the pending revert on failure to retry it at the next time.
+        Some(profile) => {
+            apply_profile(client, account, state, profile, None).await;
+        }
+        None => {
+            println!(
+                "[ERROR] {}: Profile \"{}\" is not found, reverting is cancelled.",
+                account.key, pending.profile
+            );
+            state.pending_revert = None;
+        }
+    }
+}
+
+/// the built-in variables of the profile fields, and the named groups of the trigger.
+fn variables(
+    account: &Account,
+    state: &State,
+    profile: &Profile,
+    trigger: Option<(&Status, &TriggerMatch<'_>)>,
+    now: DateTime<Utc>,
+) -> Variables {
+    let property = account.property();
+    let stats = state.profile_stats.get(&profile.key);
+    // the values after applying this time
+    let count = stats.map(|s| s.count).unwrap_or_default() + 1;
+    let since = stats.map(|s| s.since).unwrap_or(now);
+    let previous = if state.applied_profile.as_ref() == Some(&profile.key) {
+        state.previous_profile.clone()
+    } else {
+        state.applied_profile.clone()
+    };
+    let mut vars = Variables::new();
+    let mut set = |name: &str, value: String| {
+        vars.insert(name.to_string(), value);
+    };
+    set("profile", profile.key.clone());
+    set("date", property.format_time(now, "%Y-%m-%d"));
+    set("time", property.format_time(now, "%H:%M"));
+    set("count", count.to_string());
+    set("since", property.format_time(since, "%Y-%m-%d"));
+    set("previous", previous.unwrap_or_default());
+    set(
+        "tweet",
+        trigger.map(|(s, _)| s.text.clone()).unwrap_or_default(),
+    );
+    set(
+        "tweet_id",
+        trigger.map(|(s, _)| s.id.to_string()).unwrap_or_default(),
+    );
+    // the named groups override the built-in ones
+    if let Some((_, t)) = trigger {
+        vars.extend(t.captures.clone());
+    }
+    vars
+}
+
+/// apply the profile and record it into the state. returns true when succeeded.
+pub async fn apply_profile(
+    client: &dyn TwitterClient,
+    account: &Account,
+    state: &mut State,
+    profile: &Profile,
+    trigger: Option<(&Status, &TriggerMatch<'_>)>,
+) -> bool {
+    println!("{}: applying \"{}\"...", account.key, profile.key);
+    let resolved = match profile.resolve(account.profiles()) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("Invalid configuration detected: {}", e);
+            return false;
+        }
+    };
+    let now = Utc::now();
+    let vars = variables(account, state, profile, trigger, now);
+    let in_reply_to_status_id = trigger.map(|(status, _)| status.id);
+    // the images uploaded to another account are not known
+    let face = match resolved.target_auth_info {
+        Some(_) => None,
+        None => Some(&mut state.face),
+    };
+    let report = resolved
+        .apply(
+            client,
+            account,
+            in_reply_to_status_id,
+            &vars,
+            face,
+            &mut state.image_turns,
+        )
+        .await;
+    if report.is_applied() {
+        let stats = state
+            .profile_stats
+            .entry(profile.key.clone())
+            .or_insert(ProfileStats {
+                count: 0,
+                since: now,
+            });
+        stats.count += 1;
+    }
+    if !report.is_applied() {
+        println!(
+            "{}: applying \"{}\" failed X(\n{}",
+            account.key, profile.key, report
+        );
+        return false;
+    }
+    if report.results.iter().any(|(_, o)| !o.is_done()) {
+        println!(
+            "[WARN] {}: \"{}\" applied partially:\n{}",
+            account.key, profile.key, report
+        );
+    }
+    match resolved.target_auth_info {
+        Some(_) => {
+            // the face of this account is not changed
+            println!(
+                "{}: \"{}\" applied to account {}!",
+                account.key,
+                profile.key,
+                profile.target.as_deref().unwrap_or_default()
+            );
+            true
+        }
+        None => {
+            println!("{}: \"{}\" applied!", account.key, profile.key);
+            if state.applied_profile.as_ref() != Some(&profile.key) {
+                state.previous_profile = state.applied_profile.replace(profile.key.clone());
+            }
+            // schedule reverting, or cancel the previous one
+            state.pending_revert = match (resolved.ttl, resolved.revert_to) {
+                (Some(ttl), Some(revert_to)) => Some(PendingRevert {
+                    profile: revert_to.clone(),
+                    at: Utc::now() + chrono::Duration::seconds(ttl as i64),
+                }),
+                _ => None,
+            };
+            if let Some(ref pending) = state.pending_revert {
+                println!(
+                    "{}: Profile \"{}\" will be applied at {}.",
+                    account.key,
+                    pending.profile,
+                    pending.at.with_timezone(&Local)
+                );
+            }
+            true
+        }
+    }
+}
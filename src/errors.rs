@@ -23,6 +23,13 @@ impl ConfigurationError {
             message: message.into(),
         }
     }
+
+    /// add where the error is detected.
+    pub fn context<T: fmt::Display>(self, context: T) -> Self {
+        ConfigurationError {
+            message: format!("{} ({})", self.message, context),
+        }
+    }
 }
 
 impl error::Error for ConfigurationError {
@@ -0,0 +1,155 @@
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::errors::ConfigurationError;
+use crate::imaging::{self, ImageKind, ImageProcessing};
+use crate::twitter_api::{client::TwitterClient, TwitterResult};
+
+/// the extensions of the files taken from the directories.
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+/// where the image comes from. each entry is a file, a directory of images, or an HTTP(S) URL.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ImageSource {
+    One(String),
+    /// one of the images is picked at random on each apply
+    List(Vec<String>),
+    Rotation {
+        images: Vec<String>,
+        #[serde(default)]
+        pick: Pick,
+    },
+}
+
+/// how to pick one of the images.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pick {
+    #[default]
+    Random,
+    /// in turn, in the order of the list and the file names in the directories
+    RoundRobin,
+}
+
+impl From<&str> for ImageSource {
+    fn from(path: &str) -> Self {
+        ImageSource::One(path.to_string())
+    }
+}
+
+impl ImageSource {
+    fn entries(&self) -> (&[String], Pick) {
+        match self {
+            ImageSource::One(entry) => (std::slice::from_ref(entry), Pick::Random),
+            ImageSource::List(entries) => (entries, Pick::Random),
+            ImageSource::Rotation { images, pick } => (images, *pick),
+        }
+    }
+
+    /// the files and the URLs to pick from, with the directories expanded.
+    fn candidates(&self) -> std::io::Result<Vec<String>> {
+        let mut candidates = Vec::new();
+        for entry in self.entries().0 {
+            if is_url(entry) || !Path::new(entry).is_dir() {
+                candidates.push(entry.clone());
+                continue;
+            }
+            let mut files = fs::read_dir(entry)?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            files.retain(|p| {
+                p.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+            });
+            files.sort();
+            candidates.extend(files.iter().map(|p| p.to_string_lossy().into_owned()));
+        }
+        Ok(candidates)
+    }
+
+    /// check the local images. the images on the web are checked after downloading.
+    pub fn check(
+        &self,
+        kind: ImageKind,
+        processing: Option<&ImageProcessing>,
+    ) -> Result<(), ConfigurationError> {
+        let candidates = self.candidates().map_err(|e| {
+            ConfigurationError::new(format!("Image directory cannot be read: {}", e))
+        })?;
+        if candidates.is_empty() {
+            return Err(ConfigurationError::new("No image is found in the source"));
+        }
+        for candidate in candidates.iter().filter(|c| !is_url(c)) {
+            imaging::check(candidate, kind, processing)?;
+        }
+        Ok(())
+    }
+
+    /// pick an image, and returns the local path of it. the image on the web is downloaded
+    /// into `cache_dir`, or the cached one is used if not modified.
+    /// `turns` counts the picks of the round robins, keyed by `<profile>.<image|banner>`.
+    pub async fn fetch(
+        &self,
+        client: &dyn TwitterClient,
+        kind: ImageKind,
+        processing: Option<&ImageProcessing>,
+        turns: &mut BTreeMap<String, u64>,
+        profile: &str,
+    ) -> TwitterResult<String> {
+        let candidates = self.candidates()?;
+        let picked = match self.entries().1 {
+            Pick::Random => candidates.choose(&mut rand::thread_rng()),
+            Pick::RoundRobin => {
+                let turn = turns
+                    .entry(format!("{}.{}", profile, kind.name()))
+                    .or_insert(0);
+                let picked = candidates.get((*turn % candidates.len().max(1) as u64) as usize);
+                *turn += 1;
+                picked
+            }
+        };
+        let picked = match picked {
+            Some(picked) => picked,
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "no image is found in the source",
+                )
+                .into())
+            }
+        };
+        if !is_url(picked) {
+            return Ok(picked.clone());
+        }
+
+        let cache_dir = Path::new(imaging::cache_dir(processing)).join("downloads");
+        fs::create_dir_all(&cache_dir)?;
+        let path = cache_dir.join(format!("{:x}", Sha1::digest(picked.as_bytes())));
+        match client.fetch_image(picked, &path).await {
+            Ok(_) => {}
+            // the web may be down for a while
+            Err(e) if path.exists() => {
+                println!("[WARN] Using the cached image of {}: {}", picked, e);
+            }
+            Err(e) => return Err(e),
+        }
+        let path = path.to_string_lossy().into_owned();
+        imaging::check(&path, kind, processing).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} ({})", e, picked),
+            )
+        })?;
+        Ok(path)
+    }
+}
+
+fn is_url(entry: &str) -> bool {
+    entry.starts_with("https://") || entry.starts_with("http://")
+}
@@ -0,0 +1,228 @@
+use image::imageops::FilterType;
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::errors::ConfigurationError;
+
+/// the size of the avatar uploaded, which Twitter scales down the larger ones to.
+const AVATAR_SIZE: u32 = 400;
+const BANNER_WIDTH: u32 = 1500;
+const BANNER_HEIGHT: u32 = 500;
+const JPEG_QUALITY: u8 = 90;
+/// changed when the processing changes, to leave the old results in the cache.
+const PROCESS_VERSION: u32 = 1;
+
+/// processing the images before uploading them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImageProcessing {
+    /// the directory where the processed images are kept
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: String,
+}
+
+fn default_cache_dir() -> String {
+    DEFAULT_CACHE_DIR.to_string()
+}
+
+const DEFAULT_CACHE_DIR: &str = "image_cache";
+
+/// the directory of the processed and the downloaded images.
+pub fn cache_dir(processing: Option<&ImageProcessing>) -> &str {
+    processing.map_or(DEFAULT_CACHE_DIR, |p| p.cache_dir.as_str())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageKind {
+    Avatar,
+    Banner,
+}
+
+impl ImageKind {
+    /// the limit of the file size by Twitter.
+    fn max_bytes(self) -> u64 {
+        match self {
+            ImageKind::Avatar => 700 * 1024,
+            ImageKind::Banner => 5 * 1024 * 1024,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ImageKind::Avatar => "image",
+            ImageKind::Banner => "banner",
+        }
+    }
+}
+
+/// check the image is uploadable as it is, or processable if the processing is enabled.
+pub fn check(
+    path: &str,
+    kind: ImageKind,
+    processing: Option<&ImageProcessing>,
+) -> Result<(), ConfigurationError> {
+    let error = |message: String| {
+        ConfigurationError::new(format!(
+            "Specified profile {} {} {}",
+            kind.name(),
+            path,
+            message
+        ))
+    };
+    if !Path::new(path).exists() {
+        return Err(error("is not found".to_string()));
+    }
+    let data = fs::read(path).map_err(|e| error(format!("cannot be read: {}", e)))?;
+    if is_heic(&data) {
+        return Err(error(
+            "is HEIC, which is not supported. convert it to PNG or JPEG".to_string(),
+        ));
+    }
+    let reader = ImageReader::new(io::Cursor::new(&data))
+        .with_guessed_format()
+        .map_err(|e| error(format!("cannot be read: {}", e)))?;
+    let format = reader.format();
+    if processing.is_some() {
+        // anything decodable is converted
+        return match format {
+            Some(ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP) => {
+                Ok(())
+            }
+            _ => Err(error("is not PNG, JPEG, GIF nor WebP".to_string())),
+        };
+    }
+
+    if !matches!(
+        format,
+        Some(ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif)
+    ) {
+        return Err(error(
+            "is not PNG, JPEG nor GIF. enable image_processing to convert it".to_string(),
+        ));
+    }
+    if data.len() as u64 > kind.max_bytes() {
+        return Err(error(format!(
+            "is larger than {} KB. enable image_processing to shrink it",
+            kind.max_bytes() / 1024
+        )));
+    }
+    let (width, height) = reader
+        .into_dimensions()
+        .map_err(|e| error(format!("is broken: {}", e)))?;
+    // Twitter crops the others by itself
+    match kind {
+        ImageKind::Avatar if width != height => println!(
+            "[WARN] profile image {} is not square ({}x{}), and will be cropped.",
+            path, width, height
+        ),
+        ImageKind::Banner if width * BANNER_HEIGHT != height * BANNER_WIDTH => println!(
+            "[WARN] profile banner {} is not {}x{} ({}x{}), and will be cropped.",
+            path, BANNER_WIDTH, BANNER_HEIGHT, width, height
+        ),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// the path of the image to upload: the processed one in the cache if the processing is enabled,
+/// or the original one if not.
+pub fn prepare(
+    path: &str,
+    kind: ImageKind,
+    processing: Option<&ImageProcessing>,
+) -> io::Result<String> {
+    let processing = match processing {
+        Some(p) => p,
+        None => return Ok(path.to_string()),
+    };
+    let data = fs::read(path)?;
+    let key = format!(
+        "{:x}-{}-{}",
+        Sha1::digest(&data),
+        kind.name(),
+        PROCESS_VERSION
+    );
+    let cache_dir = Path::new(&processing.cache_dir);
+    for ext in ["png", "jpg"].iter() {
+        let cached = cache_dir.join(format!("{}.{}", key, ext));
+        if cached.exists() {
+            return Ok(cached.to_string_lossy().into_owned());
+        }
+    }
+
+    let image = process(&data, kind).map_err(io::Error::other)?;
+    // the transparency is kept in PNG, and the others are smaller in JPEG
+    let (ext, encoded) = if image.color().has_alpha() {
+        let mut encoded = Vec::new();
+        image
+            .write_to(&mut io::Cursor::new(&mut encoded), ImageFormat::Png)
+            .map_err(io::Error::other)?;
+        ("png", encoded)
+    } else {
+        let mut encoded = Vec::new();
+        JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY)
+            .encode_image(&image.to_rgb8())
+            .map_err(io::Error::other)?;
+        ("jpg", encoded)
+    };
+    if encoded.len() as u64 > kind.max_bytes() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "processed {} is still larger than {} KB: {}",
+                kind.name(),
+                kind.max_bytes() / 1024,
+                path
+            ),
+        ));
+    }
+    fs::create_dir_all(cache_dir)?;
+    let cached = cache_dir.join(format!("{}.{}", key, ext));
+    // not to leave a broken image in the cache
+    let temp = cached.with_extension("tmp");
+    fs::write(&temp, encoded)?;
+    fs::rename(&temp, &cached)?;
+    Ok(cached.to_string_lossy().into_owned())
+}
+
+/// decode the image, and fit it to the kind. the metadata like EXIF is dropped on the way.
+fn process(data: &[u8], kind: ImageKind) -> image::ImageResult<DynamicImage> {
+    let mut decoder = ImageReader::new(io::Cursor::new(data))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    // the rotation is lost with EXIF
+    image.apply_orientation(orientation);
+    Ok(match kind {
+        ImageKind::Avatar => {
+            let size = image.width().min(image.height());
+            let square = image.crop_imm(
+                (image.width() - size) / 2,
+                (image.height() - size) / 2,
+                size,
+                size,
+            );
+            if size > AVATAR_SIZE {
+                square.resize_exact(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3)
+            } else {
+                square
+            }
+        }
+        ImageKind::Banner => {
+            image.resize_to_fill(BANNER_WIDTH, BANNER_HEIGHT, FilterType::Lanczos3)
+        }
+    })
+}
+
+/// whether the data is HEIC or HEIF, which cannot be decoded.
+fn is_heic(data: &[u8]) -> bool {
+    data.get(4..8) == Some(&b"ftyp"[..])
+        && matches!(
+            data.get(8..12),
+            Some(b"heic" | b"heix" | b"mif1" | b"msf1" | b"hevc")
+        )
+}
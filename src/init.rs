@@ -7,64 +7,65 @@ use futures::future::TryFutureExt;
 use std::iter::Iterator;
 use std::path;
 
-pub async fn load_or_init_api_key(token_file: &str) -> Result<ApiKey, ConfigError> {
+pub async fn load_or_init_api_key(base_url: &str, token_file: &str) -> Result<ApiKey, ConfigError> {
     let token_path = path::Path::new(token_file);
     if token_path.exists() {
         ApiKey::load(token_path)
     } else {
         // create api key
         println!("token file not found -> create token file.");
-        let token = create_api_key().await?;
+        let token = create_api_key(base_url).await?;
         token.save(token_file)?;
         Ok(token)
     }
 }
 
-async fn create_api_key() -> Result<ApiKey, ConfigError> {
+async fn create_api_key(base_url: &str) -> Result<ApiKey, ConfigError> {
     async {
         acquire_user_input(&["App token", "App secret"])
             .map(|input| ApiKey::new(&input[0], &input[1]))
             .ok_or(ConfigError::UserCancelled)
     }
-    .and_then(|k| validate_api_key(k))
+    .and_then(|api_key| validate_api_key(base_url, api_key))
     .await
 }
 
-async fn validate_api_key(api_key: ApiKey) -> Result<ApiKey, ConfigError> {
-    misc::check_api_key(&api_key).await?;
+async fn validate_api_key(base_url: &str, api_key: ApiKey) -> Result<ApiKey, ConfigError> {
+    misc::check_api_key(base_url, &api_key).await?;
     Ok(api_key)
 }
 
 pub async fn load_or_init_config(
+    base_url: &str,
     api_key: &ApiKey,
     config_file: &str,
 ) -> Result<Config, ConfigError> {
     let conf_path = path::Path::new(config_file);
     if conf_path.exists() {
-        Config::load(config_file)
+        Config::open(config_file)
     } else {
         // create configuration
         println!("config file not found -> create config file.");
-        let config = create_config(api_key).await?;
+        let config = create_config(base_url, api_key).await?;
         config.save(config_file)?;
         Ok(config)
     }
 }
 
-async fn create_config(api_key: &ApiKey) -> Result<Config, ConfigError> {
-    let auth_info = create_token(api_key).await?;
+async fn create_config(base_url: &str, api_key: &ApiKey) -> Result<Config, ConfigError> {
+    let auth_info = create_token(base_url, api_key).await?;
     Ok(Config::new_example(auth_info))
 }
 
-async fn create_token(api_key: &ApiKey) -> Result<AuthInfo, ConfigError> {
-    let req_token = auth::request_token(api_key).await?;
+async fn create_token(base_url: &str, api_key: &ApiKey) -> Result<AuthInfo, ConfigError> {
+    let req_token = auth::request_token(base_url, api_key).await?;
     println!(
         "Please access to proceed the authorization: {}",
-        auth::get_authorization_url(&req_token)
+        auth::get_authorization_url(base_url, &req_token)
     );
     let pin = acquire_user_input(&["PIN"]).ok_or(ConfigError::UserCancelled)?;
     let pin = pin.into_iter().next().ok_or(ConfigError::UserCancelled)?;
-    let acc_token = auth::access_token(api_key, req_token, pin).await?;
+    let acc_token = auth::access_token(base_url, api_key, req_token, pin).await?;
     let user_id = acc_token.remain.get("user_id").unwrap();
     let user_id = user_id.parse::<u64>().unwrap();
 
@@ -75,7 +76,7 @@ async fn create_token(api_key: &ApiKey) -> Result<AuthInfo, ConfigError> {
     ))
 }
 
-fn acquire_user_input<'a>(keys: &[&'a str]) -> Option<Vec<String>> {
+fn acquire_user_input(keys: &[&str]) -> Option<Vec<String>> {
     use std::io::{stdin, stdout, Write};
     let mut inputs = Vec::new();
     loop {
@@ -106,10 +107,7 @@ fn acquire_user_input<'a>(keys: &[&'a str]) -> Option<Vec<String>> {
             if stdin().read_line(&mut input).is_ok() {
                 match input
                     .chars()
-                    .filter(|&c| {
-                        ('a' <= c && c <= 'z') || ('A' <= c && c <= 'Z') || ('0' <= c && c <= '9')
-                    })
-                    .next()
+                    .find(|c| c.is_ascii_alphanumeric())
                     .unwrap_or('y')
                 {
                     'Y' | 'y' | '\n' => return Some(inputs),
@@ -1,243 +1,200 @@
-use chrono::{Local, Utc};
-
-use errors::{ConfigurationError, Error};
-use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
-use regex::Regex;
-use twitter_api::{misc::check_user_auth, models::Status, statuses};
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
 
+mod apply;
+mod commands;
+mod condition;
 mod config;
+mod daemon;
 mod errors;
+mod image_source;
+mod imaging;
 mod init;
+mod normalize;
+mod pacer;
+mod remote;
+mod simulator;
+mod snapshot;
+mod state;
+mod template;
+#[cfg(test)]
+mod tests;
+mod trigger;
 mod twitter_api;
 
-use std::{sync::mpsc::channel, sync::mpsc::Receiver, time::Duration};
-
 use config::*;
-use tokio;
+use state::StateFile;
+use twitter_api::client::{ApiClient, TwitterClient};
+
+static STATE_FILE: &str = "state.yaml";
+
+#[derive(Parser)]
+#[command(version, about = "The Fiend with Twenty Faces on Twitter.")]
+struct Cli {
+    /// path of the configuration file
+    #[arg(long, global = true, default_value = "config.yaml")]
+    config: String,
+    /// path of the token file
+    #[arg(long, global = true, default_value = "token.yaml")]
+    token: String,
+    /// key of the account to operate, other than run
+    #[arg(long, global = true, default_value = DEFAULT_ACCOUNT_KEY)]
+    account: String,
+    /// base URL of the Twitter API
+    #[arg(long, global = true, default_value = twitter_api::API_BASE_URL)]
+    api_base_url: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-static TOKEN_FILE: &str = "token.yaml";
-static CONFIG_FILE: &str = "config.yaml";
+#[derive(Subcommand)]
+enum Command {
+    /// Watch your tweets and switch the profile (default)
+    Run,
+    /// Create the token file and the configuration file
+    Init,
+    /// Check the configuration file
+    Validate,
+    /// Apply the profile immediately
+    Apply {
+        /// key of the profile
+        profile: String,
+    },
+    /// List the profiles and the schedules
+    List,
+    /// Show the profile triggered by the text
+    DryRun {
+        /// text of the tweet
+        text: String,
+    },
+    /// Show the profiles triggered by the tweets in the file, without network access
+    Simulate {
+        /// file of the tweet texts (one per line) or the timeline JSON. stdin if omitted
+        file: Option<String>,
+        /// the timeline of the statuses in the JSON: own, mentions or home
+        #[arg(long, default_value = "own", value_parser = parse_source)]
+        source: Source,
+    },
+    /// Record the current profile as the profile "unmask"
+    Snapshot,
+    /// Restore the profile recorded by snapshot
+    Unmask,
+}
 
 #[tokio::main]
 async fn main() {
-    // check existence of config file
-    let api_key = init::load_or_init_api_key(TOKEN_FILE)
-        .await
-        .expect("failed to load the token file.");
-    // load or init configuration
-    let conf = init::load_or_init_config(&api_key, CONFIG_FILE)
-        .await
-        .expect("failed to load the configuration file.");
-    // check configuration validity
-    let conf = check_config(&api_key, conf)
-        .await
-        .expect("invalid configuration detected.");
-    main_proc(&api_key, CONFIG_FILE, conf).await;
-}
-
-async fn main_proc(api_key: &ApiKey, conf_file_path: &str, mut config: Config) {
-    // activate config file watcher
-    let (tx, rx) = channel();
-    let mut watcher: RecommendedWatcher = Watcher::new(tx, Duration::from_millis(500)).unwrap();
-    watcher
-        .watch(conf_file_path, RecursiveMode::Recursive)
-        .unwrap();
-    println!("Press CTRL+C to exit...");
-    let receive_interval = chrono::Duration::seconds(10);
-    let mut last_received_id = None;
-    loop {
-        last_received_id = recv_and_fire_trigger(&api_key, &config, last_received_id)
-            .await
-            .or(last_received_id);
-        let next_recv = Utc::now() + receive_interval;
-        // spin wait
-        loop {
-            // check configuration changes and read it
-            if let Some(new_config) = spin_until_update(&rx, conf_file_path, receive_interval).await
-            {
-                println!(
-                    "Configuration file changed and reloaded at {}",
-                    Local::now()
-                );
-                // update config if it is valid
-                config = match check_config(api_key, new_config).await {
-                    Ok(c) => c,
-                    Err(e) => {
-                        println!("[ERROR] The new token configured in the file seems be invalid.");
-                        println!("        {:?}", e);
-                        println!("        New configuration is not applied.");
-                        config
-                    }
-                };
-            }
-            // wait until we have to acquire new timeline information
-            if Utc::now() > next_recv {
-                break;
-            }
+    let mut cli = Cli::parse();
+    // the state and the snapshot are placed next to the configuration file
+    let state_file = sibling_path(&cli.config, STATE_FILE);
+    let state_file = state_file
+        .to_str()
+        .expect("invalid path of the state file.");
+    let snapshot_dir = snapshot::snapshot_dir(&cli.config, &cli.account);
+
+    match cli.command.take().unwrap_or(Command::Run) {
+        Command::Run => {
+            // check existence of config file
+            let api_key = init::load_or_init_api_key(&cli.api_base_url, &cli.token)
+                .await
+                .expect("failed to load the token file.");
+            // load or init configuration
+            let conf = init::load_or_init_config(&cli.api_base_url, &api_key, &cli.config)
+                .await
+                .expect("failed to load the configuration file.");
+            // check configuration validity
+            let client = ApiClient::new(api_key, &cli.api_base_url);
+            let conf = daemon::check_config(&client, conf)
+                .await
+                .expect("invalid configuration detected.");
+            // restore the state of the previous execution
+            let state = load_state(state_file);
+            daemon::main_proc(&client, &cli.config, state_file, conf, state).await;
+        }
+        Command::Init => commands::init(&cli.api_base_url, &cli.token, &cli.config).await,
+        Command::Validate => commands::validate(&load_client(&cli), load_config(&cli.config)).await,
+        Command::Apply { profile } => {
+            let client = load_client(&cli);
+            let conf = load_valid_config(&client, &cli.config).await;
+            let account = select_account(&conf, &cli.account);
+            commands::apply(
+                &client,
+                state_file,
+                account,
+                load_state(state_file),
+                &profile,
+            )
+            .await;
+        }
+        Command::List => {
+            let conf = load_offline_config(&cli.config);
+            commands::list(select_account(&conf, &cli.account))
+        }
+        Command::DryRun { text } => {
+            let conf = load_offline_config(&cli.config);
+            commands::dry_run(select_account(&conf, &cli.account), &text)
+        }
+        Command::Simulate { file, source } => {
+            let conf = load_offline_config(&cli.config);
+            commands::simulate(select_account(&conf, &cli.account), file.as_deref(), source)
+        }
+        Command::Snapshot => {
+            let client = load_client(&cli);
+            let conf = load_valid_config(&client, &cli.config).await;
+            select_account(&conf, &cli.account);
+            let mut state = load_state(state_file);
+            let state = state.account_mut(&cli.account);
+            commands::take_snapshot(&client, snapshot_dir, &conf, &cli.account, state).await;
+        }
+        Command::Unmask => {
+            let client = load_client(&cli);
+            let conf = load_valid_config(&client, &cli.config).await;
+            let account = select_account(&conf, &cli.account);
+            commands::unmask(&client, state_file, account, load_state(state_file)).await;
         }
     }
 }
 
-async fn check_config(api_key: &ApiKey, config: Config) -> Result<Config, Error> {
-    check_user_auth(&api_key, config.auth_info()).await.map_err(|e| ConfigurationError::new(
-        format!("Configuration error: authorization token has been invalidated or expired.\ndetail: {}", e)
-    ))?;
-    // check configuration validity
-    config.validate(&api_key).await
+fn parse_source(name: &str) -> Result<Source, String> {
+    serde_yaml::from_str(name).map_err(|_| format!("unknown source {}", name))
 }
 
-async fn spin_until_update(
-    rx: &Receiver<DebouncedEvent>,
-    conf_file_path: &str,
-    timeout: chrono::Duration,
-) -> Option<Config> {
-    let deadline = Utc::now() + timeout;
-    loop {
-        // check configuration has been changed
-        if let Some(c) = check_config_update(rx, conf_file_path) {
-            return Some(c);
-        }
-        // check
-        if Utc::now() > deadline {
-            return None;
-        }
-        // 0.1 msec await
-        tokio::time::sleep(Duration::from_millis(100)).await;
-    }
+fn sibling_path(file: &str, name: &str) -> PathBuf {
+    Path::new(file).with_file_name(name)
 }
 
-fn check_config_update(rx: &Receiver<DebouncedEvent>, conf_file_path: &str) -> Option<Config> {
-    match rx.try_recv() {
-        Ok(event) => {
-            match event {
-                // fatal errors
-                notify::DebouncedEvent::NoticeRemove(_)
-                | notify::DebouncedEvent::Remove(_)
-                | notify::DebouncedEvent::Rename(_, _) => {
-                    panic!("Configuration file had been removed and that is unexpected behavior.")
-                }
-                notify::DebouncedEvent::Error(e, p) => {
-                    panic!("Configuration watcher failed: {:?} - {:?}", e, p);
-                }
-                _ => {
-                    // maybe the file has been changed
-                    Some(
-                        Config::load(conf_file_path)
-                            .expect("Failed to read the configuration file"),
-                    )
-                }
-            }
-        }
-        Err(err) => match err {
-            std::sync::mpsc::TryRecvError::Empty => {
-                // nothing to do
-                None
-            }
-            std::sync::mpsc::TryRecvError::Disconnected => {
-                // watcher failed
-                panic!("Configuration watcher has been exited unexpectedly.")
-            }
-        },
-    }
+fn load_api_key(token_file: &str) -> ApiKey {
+    ApiKey::load(token_file)
+        .expect("failed to load the token file. run \"twentyfaces init\" to create it.")
 }
 
-async fn recv_and_fire_trigger(
-    api_key: &ApiKey,
-    config: &Config,
-    last_received: Option<u64>,
-) -> Option<u64> {
-    let recvd =
-        statuses::user_timeline(api_key, config.auth_info(), Some(200u32), last_received).await;
-    match recvd {
-        Ok(mut statuses) => {
-            // order by descending
-            statuses.sort_by_key(|f| f.id);
-            statuses.reverse();
-            // pick max id
-            let max_id = statuses.get(0).map(|s| s.id);
-            let triggered_profile = statuses
-                .into_iter()
-                .filter(|s| last_received.map(|l| s.id > l).unwrap_or(true))
-                .filter_map(|s| check_triggered_profile(&s, config).map(|t| (s, t)))
-                .next();
-
-            if let Some((status, profile)) = triggered_profile {
-                println!(
-                    "Profile \"{}\" triggered by status: {}",
-                    profile.key, status.text
-                );
-                if last_received.is_none() {
-                    println!("last_received property was not specified, so treat as dry-run mode and not triggered.");
-                } else {
-                    print!("applying...");
-                    // profile is triggered!
-                    let resolved = profile.resolve(config.profiles());
-                    match resolved {
-                        Ok(r) => match r.apply(api_key, config).await {
-                            Ok(_) => println!(" -> applied!"),
-                            Err(a) => println!(" -> failed X(\n{}", a),
-                        },
-                        Err(e) => println!("Invalid configuration detected: {}", e),
-                    }
-                }
-            }
-
-            // when profile
-            max_id
-        }
-        Err(e) => {
-            println!("[ERROR] Retriving timeline: {}", e);
-            None
-        }
-    }
+fn load_config(config_file: &str) -> Config {
+    Config::open(config_file)
+        .expect("failed to load the configuration file. run \"twentyfaces init\" to create it.")
 }
 
-fn check_triggered_profile<'a>(status: &Status, config: &'a Config) -> Option<&'a Profile> {
-    println!("r:{}", status.text);
-    if status.retweeted_status.is_some() && !config.property().trigger_retweet {
-        // this is retweet
-        return None;
-    }
-    if status.quoted_status.is_some() && !config.property().trigger_quote {
-        // this is quoted tweet
-        return None;
-    }
-    if status.in_reply_to_status_id.is_some() && !config.property().trigger_reply {
-        // this is reply
-        return None;
-    }
-    for profile in config.profiles() {
-        // triggers / patterns should not be resolved.
-        if check_with_status(&profile.triggers, status.text.as_str(), check_trigger)
-            || check_with_status(&profile.match_instances, status.text.as_str(), check_match)
-        {
-            return Some(profile);
-        }
-    }
-    return None;
+fn load_client(cli: &Cli) -> ApiClient {
+    ApiClient::new(load_api_key(&cli.token), &cli.api_base_url)
+}
+
+async fn load_valid_config(client: &dyn TwitterClient, config_file: &str) -> Config {
+    daemon::check_config(client, load_config(config_file))
+        .await
+        .expect("invalid configuration detected.")
 }
 
-fn check_with_status<T, F>(candidates: &[T], text: &str, checker: F) -> bool
-where
-    F: Fn(&T, &str) -> bool,
-{
-    candidates
-        .iter()
-        .filter_map(|item| {
-            if checker(item, text) {
-                Some(true)
-            } else {
-                None
-            }
-        })
-        .next()
-        .unwrap_or_default()
+/// load the configuration and validate it without network access.
+fn load_offline_config(config_file: &str) -> Config {
+    load_config(config_file)
+        .validate_offline()
+        .expect("invalid configuration detected.")
 }
 
-fn check_trigger<T: AsRef<str>>(trigger: &T, text: &str) -> bool {
-    text == trigger.as_ref()
+fn select_account<'a>(config: &'a Config, key: &str) -> &'a Account {
+    config
+        .account(key)
+        .unwrap_or_else(|| panic!("account {} is not found in the configuration.", key))
 }
 
-fn check_match(pattern: &Regex, text: &str) -> bool {
-    pattern.is_match(text)
+fn load_state(state_file: &str) -> StateFile {
+    StateFile::load_or_default(state_file).expect("failed to load the state file.")
 }
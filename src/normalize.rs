@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use unicode_normalization::UnicodeNormalization;
+
+/// how to normalize the tweet text and the triggers before matching them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Normalization {
+    /// Unicode NFKC, which turns full-width letters into half-width ones
+    pub nfkc: bool,
+    /// ignore the case. the patterns of `matches` become case-insensitive as well.
+    pub case_fold: bool,
+    /// remove the whitespaces at both ends
+    pub trim: bool,
+    /// turn the sequences of whitespaces into a single space
+    pub collapse_whitespace: bool,
+    /// remove the t.co links, and the URLs at the end
+    pub strip_urls: bool,
+}
+
+impl Normalization {
+    pub fn is_none(&self) -> bool {
+        *self == Normalization::default()
+    }
+
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.apply_with_case(text, self.case_fold)
+    }
+
+    /// normalize the text except for the case, for the patterns which ignore the case by
+    /// themselves and capture the groups as written.
+    pub fn apply_keeping_case<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.apply_with_case(text, false)
+    }
+
+    fn apply_with_case<'a>(&self, text: &'a str, case_fold: bool) -> Cow<'a, str> {
+        if self.is_none() {
+            return Cow::Borrowed(text);
+        }
+        let mut text: String = if self.nfkc {
+            text.nfkc().collect()
+        } else {
+            text.to_string()
+        };
+        if self.strip_urls {
+            text = strip_urls(&text);
+        }
+        if case_fold {
+            text = text.to_lowercase();
+        }
+        if self.collapse_whitespace {
+            text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        }
+        if self.trim {
+            text = text.trim().to_string();
+        }
+        Cow::Owned(text)
+    }
+}
+
+fn strip_urls(text: &str) -> String {
+    let is_url = |w: &str| w.starts_with("https://") || w.starts_with("http://");
+    let is_short_link = |w: &str| w.starts_with("https://t.co/") || w.starts_with("http://t.co/");
+    // each piece is a word followed by a whitespace
+    let mut pieces: Vec<&str> = text
+        .split_inclusive(char::is_whitespace)
+        .filter(|p| !is_short_link(p.trim_end()))
+        .collect();
+    // the trailing URLs, and the whitespaces between them
+    let trailing = pieces
+        .iter()
+        .rev()
+        .take_while(|p| p.trim_end().is_empty() || is_url(p.trim_end()))
+        .count();
+    if pieces[pieces.len() - trailing..]
+        .iter()
+        .any(|p| is_url(p.trim_end()))
+    {
+        pieces.truncate(pieces.len() - trailing);
+        pieces.concat().trim_end().to_string()
+    } else {
+        pieces.concat()
+    }
+}
@@ -0,0 +1,96 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use std::cmp;
+use std::collections::HashMap;
+
+use crate::config::Property;
+use crate::twitter_api::{models::RateLimit, TwitterError};
+
+/// the longest interval of the backoff.
+const MAX_BACKOFF_SECONDS: i64 = 15 * 60;
+
+/// decides when to poll the timeline next, following the rate limit and the failures.
+#[derive(Debug, Default)]
+pub struct Pacer {
+    /// rate limits keyed by the resource name
+    rate_limits: HashMap<String, RateLimit>,
+    /// the consecutive failures keyed by the resource name
+    failures: HashMap<String, u32>,
+    /// the ratio of the jitter in the backoff, chosen on each failure
+    jitter: f64,
+    polled_at: Option<DateTime<Utc>>,
+}
+
+impl Pacer {
+    pub fn set_rate_limit(&mut self, resource: &str, rate_limit: RateLimit) {
+        self.rate_limits.insert(resource.to_string(), rate_limit);
+    }
+
+    pub fn on_success(&mut self, resource: &str, rate_limit: Option<RateLimit>) {
+        self.failures.remove(resource);
+        if let Some(rate_limit) = rate_limit {
+            self.set_rate_limit(resource, rate_limit);
+        }
+    }
+
+    pub fn on_error(&mut self, resource: &str, error: &TwitterError) {
+        if !error.is_transient() {
+            return;
+        }
+        *self.failures.entry(resource.to_string()).or_insert(0) += 1;
+        self.jitter = rand::thread_rng().gen();
+        if let TwitterError::Twitter(e) = error {
+            if let Some(ref rate_limit) = e.rate_limit {
+                self.set_rate_limit(resource, rate_limit.clone());
+            }
+        }
+    }
+
+    pub fn start(&mut self, now: DateTime<Utc>) {
+        self.polled_at = Some(now);
+    }
+
+    /// the time of the next poll, or None if never polled.
+    pub fn next_poll(&self, property: &Property) -> Option<DateTime<Utc>> {
+        self.polled_at
+            .map(|t| t + self.next_interval(property.receive_interval_at(t), t))
+    }
+
+    pub fn is_due(&self, property: &Property, now: DateTime<Utc>) -> bool {
+        self.next_poll(property).map(|t| t <= now).unwrap_or(true)
+    }
+
+    /// the interval until the next poll. it is `base` unless the rate limit is near or the
+    /// requests have been failing.
+    pub fn next_interval(&self, base: Duration, now: DateTime<Utc>) -> Duration {
+        let mut interval = base;
+        // follow the most restricted one
+        for limit in self.rate_limits.values() {
+            if limit.reset > now {
+                // spread the remaining requests until the reset, keeping the last one.
+                let until_reset = limit.reset - now;
+                interval = match limit.remaining {
+                    0 | 1 => cmp::max(interval, until_reset + Duration::seconds(1)),
+                    remaining => cmp::max(
+                        interval,
+                        until_reset / (cmp::min(remaining - 1, i32::MAX as u64) as i32),
+                    ),
+                };
+            }
+        }
+        // back off for the resource failing the most
+        let failures = self.failures.values().copied().max().unwrap_or(0);
+        if failures > 0 {
+            // exponential backoff with jitter in its upper half
+            let max_backoff = Duration::seconds(MAX_BACKOFF_SECONDS);
+            let backoff = cmp::min(
+                base * 2i32.saturating_pow(cmp::min(failures, 16)),
+                max_backoff,
+            );
+            let half = backoff.num_milliseconds() / 2;
+            let jitter = (half as f64 * self.jitter) as i64;
+            interval = cmp::max(interval, Duration::milliseconds(half + jitter));
+        }
+        interval
+    }
+}
@@ -0,0 +1,188 @@
+use chrono::Local;
+
+use crate::config::Account;
+use crate::daemon::apply_profile;
+use crate::pacer::Pacer;
+use crate::state::{State, NOTHING_RECEIVED};
+use crate::twitter_api::client::TwitterClient;
+use crate::twitter_api::models::{DirectMessage, DIRECT_MESSAGES_RESOURCE};
+
+/// the maximum number of the direct messages in a page.
+const DIRECT_MESSAGES_COUNT: u32 = 50;
+
+static USAGE: &str = "commands: apply <profile>, status, revert, pause, resume";
+
+/// the command sent by a direct message.
+#[derive(Debug, PartialEq)]
+pub enum Command<'a> {
+    /// apply the profile
+    Apply(&'a str),
+    /// show the applied profile
+    Status,
+    /// apply the previous profile
+    Revert,
+    /// stop applying profiles by tweets, schedules and expiration
+    Pause,
+    Resume,
+}
+
+impl<'a> Command<'a> {
+    pub fn parse(text: &'a str) -> Option<Command<'a>> {
+        let mut words = text.split_whitespace();
+        let name = words.next()?.to_lowercase();
+        let command = match (name.as_str(), words.next()) {
+            ("apply", Some(key)) => Command::Apply(key),
+            ("status", None) => Command::Status,
+            ("revert", None) => Command::Revert,
+            ("pause", None) => Command::Pause,
+            ("resume", None) => Command::Resume,
+            _ => return None,
+        };
+        match words.next() {
+            Some(_) => None,
+            None => Some(command),
+        }
+    }
+}
+
+/// run the commands sent by the allowed users, and reply the results.
+/// returns true when a profile is applied.
+pub async fn recv_and_run_commands(
+    client: &dyn TwitterClient,
+    account: &Account,
+    state: &mut State,
+    pacer: &mut Pacer,
+) -> bool {
+    let allowed = &account.property().dm_users;
+    if allowed.is_empty() {
+        return false;
+    }
+    let list = match client
+        .direct_messages(account.auth_info(), Some(DIRECT_MESSAGES_COUNT))
+        .await
+    {
+        Ok(list) => {
+            pacer.on_success(DIRECT_MESSAGES_RESOURCE, list.rate_limit.clone());
+            list
+        }
+        Err(e) => {
+            println!(
+                "[ERROR] {}: Retrieving the direct messages: {}",
+                account.key, e
+            );
+            pacer.on_error(DIRECT_MESSAGES_RESOURCE, &e);
+            return false;
+        }
+    };
+    let last_received = state.last_direct_message_id;
+    state.last_direct_message_id = list
+        .messages
+        .iter()
+        .map(|m| m.id)
+        .max()
+        .or(last_received)
+        .or(Some(NOTHING_RECEIVED));
+    let last_received = match last_received {
+        Some(id) => id,
+        // the messages before starting are not commands
+        None => return false,
+    };
+    let mut messages: Vec<DirectMessage> = list
+        .messages
+        .into_iter()
+        .filter(|m| m.id > last_received)
+        .filter(|m| m.recipient_id == account.auth_info().user_id && allowed.contains(&m.sender_id))
+        .collect();
+    // run in the order of sending
+    messages.sort_by_key(|m| m.id);
+
+    let mut applied = false;
+    for message in messages {
+        println!(
+            "{}: Command from the user {}: {}",
+            account.key, message.sender_id, message.text
+        );
+        let reply = match Command::parse(&message.text) {
+            Some(command) => {
+                let (reply, done) = run(client, account, state, command).await;
+                applied |= done;
+                reply
+            }
+            None => format!("Unknown command. {}", USAGE),
+        };
+        if let Err(e) = client
+            .send_direct_message(account.auth_info(), message.sender_id, &reply)
+            .await
+        {
+            println!("[ERROR] {}: Replying the command: {}", account.key, e);
+        }
+    }
+    applied
+}
+
+/// returns the reply, and whether a profile is applied.
+async fn run(
+    client: &dyn TwitterClient,
+    account: &Account,
+    state: &mut State,
+    command: Command<'_>,
+) -> (String, bool) {
+    match command {
+        Command::Apply(key) => apply_by_key(client, account, state, key).await,
+        Command::Status => (describe(state), false),
+        Command::Revert => match state.previous_profile.clone() {
+            Some(key) => apply_by_key(client, account, state, &key).await,
+            None => ("No profile to revert.".to_string(), false),
+        },
+        Command::Pause => {
+            state.paused = true;
+            (
+                "Paused. Tweets, schedules and expiration are ignored until resume.".to_string(),
+                false,
+            )
+        }
+        Command::Resume => {
+            state.paused = false;
+            ("Resumed.".to_string(), false)
+        }
+    }
+}
+
+async fn apply_by_key(
+    client: &dyn TwitterClient,
+    account: &Account,
+    state: &mut State,
+    key: &str,
+) -> (String, bool) {
+    match account.profiles().iter().find(|p| p.key == key) {
+        Some(profile) => {
+            if apply_profile(client, account, state, profile, None).await {
+                (format!("Profile \"{}\" is applied.", key), true)
+            } else {
+                (format!("Failed to apply the profile \"{}\".", key), false)
+            }
+        }
+        None => (format!("Profile \"{}\" is not found.", key), false),
+    }
+}
+
+fn describe(state: &State) -> String {
+    let mut lines = vec![format!(
+        "Applied: {}",
+        state.applied_profile.as_deref().unwrap_or("(none)")
+    )];
+    if let Some(ref previous) = state.previous_profile {
+        lines.push(format!("Previous: {}", previous));
+    }
+    if let Some(ref pending) = state.pending_revert {
+        lines.push(format!(
+            "Reverting to {} at {}",
+            pending.profile,
+            pending.at.with_timezone(&Local)
+        ));
+    }
+    if state.paused {
+        lines.push("Paused".to_string());
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,81 @@
+use std::io::Read;
+
+use crate::config::{Account, Source};
+use crate::errors::Error;
+use crate::trigger::{check_triggered_profile, check_triggered_profile_text, TriggerMatch};
+use crate::twitter_api::models::Status;
+
+/// read the tweets and show which profile would be triggered, without network access.
+/// the input is either the timeline JSON from the API or tweet texts separated by lines.
+pub fn simulate<R: Read>(account: &Account, mut input: R, source: Source) -> Result<(), Error> {
+    let mut payload = String::new();
+    input
+        .read_to_string(&mut payload)
+        .map_err(crate::twitter_api::TwitterError::from)?;
+    for (text, triggered) in evaluate(account, &payload, source)? {
+        report(account, &text, triggered);
+    }
+    Ok(())
+}
+
+/// the text of each tweet in the input, and the profile triggered by it. the statuses in the
+/// timeline JSON are evaluated as the ones from `source`, while the texts are evaluated
+/// regardless of the source.
+pub fn evaluate<'a>(
+    account: &'a Account,
+    payload: &str,
+    source: Source,
+) -> Result<Vec<(String, Option<TriggerMatch<'a>>)>, Error> {
+    let trimmed = payload.trim_start();
+    if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        Ok(Status::deserialize_timeline(trimmed)?
+            .into_iter()
+            .map(|status| {
+                let triggered = check_triggered_profile(&status, source, account);
+                (status.text, triggered)
+            })
+            .collect())
+    } else {
+        Ok(payload
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|line| {
+                (
+                    line.to_string(),
+                    check_triggered_profile_text(line, account),
+                )
+            })
+            .collect())
+    }
+}
+
+pub fn simulate_text(account: &Account, text: &str) {
+    report(account, text, check_triggered_profile_text(text, account));
+}
+
+fn report(account: &Account, text: &str, triggered: Option<TriggerMatch>) {
+    println!("> {}", text);
+    match triggered {
+        Some(TriggerMatch {
+            profile,
+            rule,
+            captures,
+        }) => {
+            println!("  profile \"{}\" is triggered by {}.", profile.key, rule);
+            match profile.resolve(account.profiles()) {
+                Ok(resolved) => {
+                    println!("  {:#?}", resolved);
+                    if !captures.is_empty() {
+                        println!("  captures: {:?}", captures);
+                        match resolved.render(&captures) {
+                            Ok(fields) => println!("  {:#?}", fields),
+                            Err(e) => println!("  [ERROR] {}", e),
+                        }
+                    }
+                }
+                Err(e) => println!("  [ERROR] {}", e),
+            }
+        }
+        None => println!("  no profile is triggered."),
+    }
+}
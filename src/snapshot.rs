@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Account, Clearable, ConfigError, Profile, SaveAndLoad, DEFAULT_ACCOUNT_KEY};
+use crate::errors::Error;
+use crate::image_source::ImageSource;
+use crate::twitter_api::client::TwitterClient;
+
+/// the key of the profile which holds the original face of the account.
+pub static UNMASK_PROFILE_KEY: &str = "unmask";
+/// the directory of the snapshots, placed next to the configuration file.
+static SNAPSHOT_DIR: &str = "snapshot";
+/// the file of the snapshot profile in the directory of the account.
+static SNAPSHOT_FILE: &str = "unmask.yaml";
+
+/// the directory of the snapshot of the account. the one of the default account is
+/// `snapshot`, and the others are `snapshot/<key>`.
+pub fn snapshot_dir<P: AsRef<Path>>(config_file: P, account_key: &str) -> PathBuf {
+    let mut dir = config_file.as_ref().with_file_name(SNAPSHOT_DIR);
+    if account_key != DEFAULT_ACCOUNT_KEY {
+        dir.push(account_key);
+    }
+    dir
+}
+
+/// save the snapshot profile into its own file, leaving the configuration file as it is.
+/// returns the path of the file.
+pub fn save<P: AsRef<Path>>(profile: &Profile, snapshot_dir: P) -> Result<PathBuf, ConfigError> {
+    fs::create_dir_all(snapshot_dir.as_ref())?;
+    let path = snapshot_dir.as_ref().join(SNAPSHOT_FILE);
+    profile.save_atomic(&path)?;
+    Ok(path)
+}
+
+/// the snapshot profile saved in the directory, if taken.
+pub fn load<P: AsRef<Path>>(snapshot_dir: P) -> Result<Option<Profile>, ConfigError> {
+    let path = snapshot_dir.as_ref().join(SNAPSHOT_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(Profile {
+        snapshot: true,
+        ..Profile::load(path)?
+    }))
+}
+
+/// record the current profile of the account as a profile named "unmask".
+/// images are downloaded into `image_dir`.
+pub async fn take_snapshot<P: AsRef<Path>>(
+    client: &dyn TwitterClient,
+    account: &Account,
+    image_dir: P,
+) -> Result<Profile, Error> {
+    let user = client.verify_credentials(account.auth_info()).await?;
+    fs::create_dir_all(image_dir.as_ref()).map_err(crate::twitter_api::TwitterError::from)?;
+
+    let image = match user.original_profile_image_url() {
+        Some(url) => Some(
+            client
+                .download_image(&url, &image_dir.as_ref().join("image"))
+                .await?,
+        ),
+        None => None,
+    };
+    let banner = match user.profile_banner_url {
+        // acquire the banner in the original size
+        Some(ref url) => Some(
+            client
+                .download_image(
+                    &format!("{}/1500x500", url),
+                    &image_dir.as_ref().join("banner"),
+                )
+                .await?,
+        ),
+        None => None,
+    };
+
+    Ok(Profile {
+        key: UNMASK_PROFILE_KEY.to_string(),
+        name: Some(user.name.clone()),
+        // the empty fields are cleared on restoring.
+        url: Some(clearable(user.expanded_url().unwrap_or_default())),
+        location: Some(clearable(user.location.clone().unwrap_or_default())),
+        description: Some(clearable(user.expanded_description())),
+        image: image.map(|p| ImageSource::One(p.to_string_lossy().into_owned())),
+        banner: Some(match banner {
+            Some(p) => Clearable::Set(ImageSource::One(p.to_string_lossy().into_owned())),
+            None => Clearable::Clear,
+        }),
+        ..Default::default()
+    })
+}
+
+fn clearable(value: String) -> Clearable {
+    if value.is_empty() {
+        Clearable::Clear
+    } else {
+        Clearable::Set(value)
+    }
+}
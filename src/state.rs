@@ -0,0 +1,168 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::config::{ConfigError, SaveAndLoad, Source, DEFAULT_ACCOUNT_KEY};
+
+/// the states of the accounts stored in the state file.
+/// the state of the default account is placed at the top level to keep the old format.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StateFile {
+    #[serde(flatten)]
+    default: State,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    accounts: BTreeMap<String, State>,
+}
+
+impl SaveAndLoad for StateFile {}
+
+impl StateFile {
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> Result<StateFile, ConfigError> {
+        if path.as_ref().exists() {
+            StateFile::load(path)
+        } else {
+            Ok(Default::default())
+        }
+    }
+
+    /// the state of the account, created if not exists.
+    pub fn account_mut(&mut self, key: &str) -> &mut State {
+        if key == DEFAULT_ACCOUNT_KEY {
+            &mut self.default
+        } else {
+            self.accounts.entry(key.to_string()).or_default()
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut State)> {
+        std::iter::once((DEFAULT_ACCOUNT_KEY, &mut self.default))
+            .chain(self.accounts.iter_mut().map(|(k, s)| (k.as_str(), s)))
+    }
+}
+
+/// the ID recorded when the first fetch receives nothing, as the IDs start from 1. the items
+/// received after it are not the ones before starting.
+pub const NOTHING_RECEIVED: u64 = 0;
+
+/// Runtime state of an account which should survive restarts.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct State {
+    /// the newest status ID which has been processed
+    pub last_status_id: Option<u64>,
+    /// the newest status ID in the mentions which has been processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_mention_id: Option<u64>,
+    /// the newest status ID in the home timeline which has been processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_home_id: Option<u64>,
+    /// the newest direct message ID which has been processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_direct_message_id: Option<u64>,
+    /// the key of the profile applied most recently
+    pub applied_profile: Option<String>,
+    /// the key of the profile applied before `applied_profile`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_profile: Option<String>,
+    /// whether the triggers, the schedules and the expiration are paused
+    #[serde(default)]
+    pub paused: bool,
+    /// the time when this state has been updated
+    pub updated_at: Option<DateTime<Utc>>,
+    /// the profile which should be applied when the current profile expires
+    pub pending_revert: Option<PendingRevert>,
+    /// the time when the schedules has been checked
+    pub last_schedule_check: Option<DateTime<Utc>>,
+    /// the trigger tweets which should be deleted, retried at the next polls
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending_deletes: Vec<u64>,
+    /// how many times and since when each profile has been applied
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile_stats: BTreeMap<String, ProfileStats>,
+    /// the images uploaded to the account, to skip uploading the same ones
+    #[serde(default, skip_serializing_if = "Face::is_empty")]
+    pub face: Face,
+    /// the next turns of the round robins of the images, keyed by `<profile>.<image|banner>`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub image_turns: BTreeMap<String, u64>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PendingRevert {
+    pub profile: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProfileStats {
+    pub count: u64,
+    /// the time when the profile has been applied at the first time
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Face {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<UploadedImage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<UploadedImage>,
+}
+
+impl Face {
+    pub fn is_empty(&self) -> bool {
+        self.image.is_none() && self.banner.is_none()
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct UploadedImage {
+    /// SHA-1 of the file in hex
+    pub sha1: String,
+    /// the URL of the image seen after uploading. the image has been replaced by someone else
+    /// if the URL is changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl UploadedImage {
+    /// whether the image is the uploaded one, and still live at the URL.
+    /// the URL is recorded if not known yet, which is only when it failed to be seen after
+    /// uploading.
+    pub fn check_live(&mut self, sha1: &str, live_url: Option<&str>) -> bool {
+        match (&self.url, live_url) {
+            _ if self.sha1 != sha1 => false,
+            // the image has been removed
+            (_, None) => false,
+            (Some(url), Some(live_url)) => url == live_url,
+            (None, Some(live_url)) => {
+                self.url = Some(live_url.to_string());
+                true
+            }
+        }
+    }
+}
+
+impl State {
+    /// whether the state has been changed, except for the time of the schedule check
+    /// which changes every time.
+    pub fn differs_from(&self, other: &State) -> bool {
+        *self
+            != State {
+                last_schedule_check: self.last_schedule_check,
+                ..other.clone()
+            }
+    }
+
+    /// the newest status ID which has been processed in the source.
+    pub fn last_received(&mut self, source: Source) -> &mut Option<u64> {
+        match source {
+            Source::Own => &mut self.last_status_id,
+            Source::Mentions => &mut self.last_mention_id,
+            Source::Home => &mut self.last_home_id,
+        }
+    }
+
+    pub fn touch(&mut self) {
+        self.updated_at = Some(Utc::now());
+    }
+}
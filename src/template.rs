@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+/// the values of the placeholders like `{role}` in the profile fields.
+pub type Variables = HashMap<String, String>;
+
+/// fill the placeholders in the template. unknown placeholders are left as they are, so
+/// the texts which happen to contain braces are not broken.
+pub fn render(template: &str, vars: &Variables) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        rendered.push_str(&rest[..open]);
+        let candidate = &rest[open..];
+        match candidate[1..]
+            .find('}')
+            .and_then(|close| vars.get(&candidate[1..close + 1]).map(|v| (close, v)))
+        {
+            Some((close, value)) => {
+                rendered.push_str(value);
+                rest = &candidate[close + 2..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &candidate[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
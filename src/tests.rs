@@ -0,0 +1,1745 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::commands;
+use crate::condition::Condition;
+use crate::config::*;
+use crate::daemon::{poll_accounts, process};
+use crate::image_source::{ImageSource, Pick};
+use crate::imaging::{self, ImageKind, ImageProcessing};
+use crate::normalize::Normalization;
+use crate::pacer::Pacer;
+use crate::remote::Command;
+use crate::simulator;
+use crate::snapshot;
+use crate::state::{Face, ProfileStats, State, StateFile};
+use crate::template::{render, Variables};
+use crate::twitter_api::client::TwitterClient;
+use crate::twitter_api::fake::{status_json, status_json_by, Call, FakeClient};
+use crate::twitter_api::misc;
+use crate::twitter_api::models::{RateLimit, DIRECT_MESSAGES_RESOURCE, USER_TIMELINE_RESOURCE};
+
+/// the default account of the configuration with the profiles.
+fn account(profiles: Vec<Profile>) -> Account {
+    account_with(Property::create_sample(), profiles)
+}
+
+/// the default account of the configuration with the property and the profiles.
+fn account_with(property: Property, profiles: Vec<Profile>) -> Account {
+    let config = Config::new(AuthInfo::new(1, "token", "secret"), property, &profiles)
+        .validate_offline()
+        .expect("invalid configuration");
+    config.accounts()[0].clone()
+}
+
+fn profile(key: &str, trigger: &str) -> Profile {
+    Profile {
+        key: key.to_string(),
+        // some change, or nothing is sent
+        name: Some(key.to_string()),
+        triggers: vec![trigger.to_string()],
+        ..Default::default()
+    }
+}
+
+fn profile_updates(client: &FakeClient) -> Vec<Call> {
+    client
+        .calls()
+        .into_iter()
+        .filter(|c| matches!(c, Call::UpdateProfile { .. }))
+        .collect()
+}
+
+/// a blank PNG image in the directory.
+fn image_file(dir: &Path, name: &str, width: u32, height: u32) -> String {
+    let path = dir.join(name);
+    image::RgbImage::new(width, height).save(&path).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+/// a new temporary directory, removed with its files when dropped.
+struct TempDir(PathBuf);
+
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn temp_dir() -> TempDir {
+    let dir = std::env::temp_dir().join(format!("twentyfaces-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    TempDir(dir)
+}
+
+async fn poll(client: &FakeClient, account: &Account, state: &mut State) -> Pacer {
+    let mut pacer = Pacer::default();
+    process(client, account, state, &mut pacer).await;
+    pacer
+}
+
+fn state_after(last_status_id: u64) -> State {
+    State {
+        last_status_id: Some(last_status_id),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn first_poll_is_dry_run() {
+    let client = FakeClient::new();
+    let account = account(vec![Profile::create_sample()]);
+    let mut state = State::default();
+    client.tweet(10, "sample");
+
+    poll(&client, &account, &mut state).await;
+
+    assert!(profile_updates(&client).is_empty());
+    assert_eq!(state.last_status_id, Some(10));
+    assert_eq!(state.applied_profile, None);
+}
+
+#[tokio::test]
+async fn trigger_applies_profile_and_replies_intro() {
+    let client = FakeClient::new();
+    let account = account(vec![Profile::create_sample()]);
+    let mut state = state_after(10);
+    client.tweet(10, "sample");
+    client.tweet(11, "please change to sample");
+
+    poll(&client, &account, &mut state).await;
+
+    assert_eq!(
+        profile_updates(&client),
+        vec![Call::UpdateProfile {
+            name: Some("sample profile".to_string()),
+            url: Some("example.com".to_string()),
+            location: Some("sample location".to_string()),
+            description: Some("this is sample profile.".to_string()),
+        }]
+    );
+    assert!(client.calls().contains(&Call::UpdateStatus {
+        status: "Hello, I'm a example profile!".to_string(),
+        in_reply_to_status_id: Some(11),
+    }));
+    assert_eq!(state.last_status_id, Some(11));
+    assert_eq!(state.applied_profile.as_deref(), Some("sample"));
+}
+
+#[tokio::test]
+async fn newest_trigger_wins_on_catch_up() {
+    let client = FakeClient::new();
+    let account = account(vec![profile("day", "day"), profile("night", "night")]);
+    let mut state = state_after(10);
+    client.tweet(11, "night");
+    client.tweet(12, "day");
+
+    poll(&client, &account, &mut state).await;
+
+    assert_eq!(profile_updates(&client).len(), 1);
+    assert_eq!(state.applied_profile.as_deref(), Some("day"));
+}
+
+#[tokio::test]
+async fn retweet_does_not_trigger() {
+    let client = FakeClient::new();
+    let account = account(vec![profile("night", "night")]);
+    let mut state = state_after(10);
+    let mut retweet = status_json(11, "night");
+    retweet["retweeted_status"] = status_json(5, "night");
+    client.push_status(retweet);
+
+    poll(&client, &account, &mut state).await;
+
+    assert!(profile_updates(&client).is_empty());
+    assert_eq!(state.last_status_id, Some(11));
+}
+
+#[tokio::test]
+async fn derived_profile_is_applied_with_inherited_fields() {
+    let client = FakeClient::new();
+    let base = Profile {
+        name: Some("base".to_string()),
+        description: Some("base description".into()),
+        ..profile("base", "base")
+    };
+    let derived = Profile {
+        derive: Some("base".to_string()),
+        name: Some("derived".to_string()),
+        ..profile("derived", "derived")
+    };
+    let account = account(vec![base, derived]);
+
+    let resolved = account.profiles()[1].resolve(account.profiles()).unwrap();
+    let report = resolved
+        .apply(
+            &client,
+            &account,
+            None,
+            &Variables::new(),
+            None,
+            &mut BTreeMap::new(),
+        )
+        .await;
+    assert!(report.is_applied());
+
+    assert_eq!(
+        profile_updates(&client),
+        vec![Call::UpdateProfile {
+            name: Some("derived".to_string()),
+            url: None,
+            location: None,
+            description: Some("base description".to_string()),
+        }]
+    );
+}
+
+#[tokio::test]
+async fn expired_profile_reverts() {
+    let client = FakeClient::new();
+    let on_call = Profile {
+        name: Some("on call".to_string()),
+        ttl: Some(0),
+        revert_to: Some("default".to_string()),
+        ..profile("on-call", "on call")
+    };
+    let default = Profile {
+        name: Some("default".to_string()),
+        ..profile("default", "default")
+    };
+    let account = account(vec![on_call, default]);
+    let mut state = state_after(10);
+    client.tweet(11, "on call");
+
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("on-call"));
+    assert!(state.pending_revert.is_some());
+
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("default"));
+    assert_eq!(state.pending_revert, None);
+    assert_eq!(profile_updates(&client).len(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_apply_is_not_recorded() {
+    let client = FakeClient::new();
+    let account = account(vec![profile("night", "night")]);
+    let mut state = state_after(10);
+    client.fail("update_profile");
+    client.tweet(11, "night");
+
+    poll(&client, &account, &mut state).await;
+
+    assert_eq!(state.applied_profile, None);
+    // the trigger is consumed anyway
+    assert_eq!(state.last_status_id, Some(11));
+}
+
+#[tokio::test]
+async fn validate_checks_credentials() {
+    let client = FakeClient::new();
+    let config = Config::new(
+        AuthInfo::default(),
+        Property::create_sample(),
+        &[Profile::create_sample()],
+    );
+    client.fail("check_user_auth");
+
+    assert!(config.validate(&client).await.is_err());
+    assert_eq!(client.calls(), vec![Call::CheckUserAuth]);
+}
+
+#[tokio::test]
+async fn interval_stretches_near_rate_limit() {
+    let client = FakeClient::new();
+    let account = account(vec![profile("night", "night")]);
+    let mut state = state_after(10);
+    let now = Utc::now();
+    client.set_rate_limit(RateLimit {
+        limit: 900,
+        remaining: 3,
+        reset: now + Duration::seconds(600),
+    });
+
+    let pacer = poll(&client, &account, &mut state).await;
+
+    let base = Duration::seconds(10);
+    assert!(pacer.next_interval(base, now) >= Duration::seconds(300));
+    // the limit has been reset
+    assert_eq!(
+        pacer.next_interval(base, now + Duration::seconds(601)),
+        base
+    );
+}
+
+#[tokio::test]
+async fn server_errors_back_off_until_success() {
+    let client = FakeClient::new();
+    let account = account(vec![profile("night", "night")]);
+    let mut state = state_after(10);
+    client.fail("user_timeline");
+
+    let mut pacer = Pacer::default();
+    let base = Duration::seconds(10);
+    let now = Utc::now();
+    process(&client, &account, &mut state, &mut pacer).await;
+    assert!(pacer.next_interval(base, now) >= base);
+    process(&client, &account, &mut state, &mut pacer).await;
+    assert!(pacer.next_interval(base, now) >= base * 2);
+    assert!(pacer.next_interval(base, now) <= base * 4);
+
+    // the success of another resource does not reset the backoff
+    pacer.on_success(DIRECT_MESSAGES_RESOURCE, None);
+    assert!(pacer.next_interval(base, now) >= base * 2);
+    pacer.on_success(USER_TIMELINE_RESOURCE, None);
+    assert_eq!(pacer.next_interval(base, now), base);
+}
+
+#[test]
+fn quiet_hours_slow_down_polling_across_midnight() {
+    let property: Property = serde_yaml::from_str(
+        r##"
+trigger_retweet: false
+trigger_quote: false
+trigger_reply: false
+quiet_hours:
+  start: "23:00"
+  end: "07:00"
+  interval: 300
+  timezone: Asia/Tokyo
+"##,
+    )
+    .unwrap();
+    let config = Config::new(AuthInfo::default(), property, &[])
+        .validate_offline()
+        .unwrap();
+    let property = config.accounts()[0].property();
+    let at = |rfc3339: &str| {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .with_timezone(&Utc)
+    };
+
+    assert_eq!(property.fetch_count, 200);
+    assert_eq!(
+        property.receive_interval_at(at("2022-01-01T22:59:00+09:00")),
+        Duration::seconds(10)
+    );
+    assert_eq!(
+        property.receive_interval_at(at("2022-01-01T23:00:00+09:00")),
+        Duration::seconds(300)
+    );
+    assert_eq!(
+        property.receive_interval_at(at("2022-01-02T06:59:00+09:00")),
+        Duration::seconds(300)
+    );
+    assert_eq!(
+        property.receive_interval_at(at("2022-01-02T07:00:00+09:00")),
+        Duration::seconds(10)
+    );
+}
+
+#[test]
+fn accounts_share_profile_sets_and_default_property() {
+    let config: Config = serde_yaml::from_str(
+        r##"
+auth_info: { user_id: 1, token: t1, secret: s1 }
+property: { trigger_retweet: false, trigger_quote: false, trigger_reply: false, receive_interval: 30 }
+profiles:
+  - { key: own, matches: [], triggers: [own] }
+profile_sets:
+  - key: shared
+    profiles:
+      - { key: night, matches: [], triggers: [night] }
+accounts:
+  - key: team
+    auth_info: { user_id: 2, token: t2, secret: s2 }
+    profile_sets: [shared]
+"##,
+    )
+    .unwrap();
+    let config = config.validate_offline().unwrap();
+
+    let keys = |a: &Account| {
+        a.profiles()
+            .iter()
+            .map(|p| p.key.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(config.accounts().len(), 2);
+    assert_eq!(keys(config.account(DEFAULT_ACCOUNT_KEY).unwrap()), ["own"]);
+    let team = config.account("team").unwrap();
+    assert_eq!(keys(team), ["night"]);
+    assert_eq!(team.auth_info().user_id, 2);
+    assert_eq!(team.property().receive_interval, 30);
+}
+
+/// the default account with the schedules of `(cron, profile)` in UTC.
+fn scheduled_account(schedules: &[(&str, &str)]) -> Result<Account, crate::errors::Error> {
+    let schedules: String = schedules
+        .iter()
+        .map(|(cron, profile)| {
+            format!(
+                "  - {{ cron: \"{}\", timezone: UTC, profile: {} }}\n",
+                cron, profile
+            )
+        })
+        .collect();
+    let config: Config = serde_yaml::from_str(&format!(
+        r##"
+auth_info: {{ user_id: 1, token: t1, secret: s1 }}
+property: {{ trigger_retweet: false, trigger_quote: false, trigger_reply: false }}
+profiles:
+  - {{ key: day, name: day, triggers: [day] }}
+  - {{ key: night, name: night, triggers: [night] }}
+schedules:
+{}"##,
+        schedules
+    ))
+    .unwrap();
+    Ok(config.validate_offline()?.accounts()[0].clone())
+}
+
+#[test]
+fn standard_cron_weekdays_count_from_sunday() {
+    // 2024-01-01 is Monday
+    let fires_on = |cron: &str| {
+        let account = scheduled_account(&[(cron, "day")]).unwrap();
+        let schedule = &account.schedules()[0];
+        (0..7)
+            .filter(|day| {
+                let from: DateTime<Utc> = "2024-01-01T00:00:00Z".parse().unwrap();
+                let from = from + Duration::days(*day);
+                schedule
+                    .last_fire_between(from, from + Duration::days(1) - Duration::seconds(1))
+                    .is_some()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(fires_on("0 9 * * 1-5"), [0, 1, 2, 3, 4]);
+    assert_eq!(fires_on("0 9 * * 0"), [6]);
+    assert_eq!(fires_on("0 9 * * 7"), [6]);
+    assert_eq!(fires_on("0 9 * * 5-7"), [4, 5, 6]);
+    assert_eq!(fires_on("0 9 * * 0,3"), [2, 6]);
+    assert_eq!(fires_on("0 9 * * 0-6/2"), [1, 3, 5, 6]);
+    assert_eq!(fires_on("0 9 * * sat,sun"), [5, 6]);
+    // the seconds field is the syntax of cron crate, where 1 is Sunday
+    assert_eq!(fires_on("0 0 9 * * 1"), [6]);
+    assert!(scheduled_account(&[("0 9 * * 8", "day")]).is_err());
+    assert!(scheduled_account(&[("0 9 * * 1-7/2", "day")]).is_err());
+}
+
+#[test]
+fn latest_schedule_is_due() {
+    let account = scheduled_account(&[("0 8 * * *", "day"), ("0 23 * * *", "night")]).unwrap();
+    let at = |time: &str| time.parse::<DateTime<Utc>>().unwrap();
+
+    let due = |from, to| {
+        account
+            .due_schedule(at(from), at(to))
+            .map(|(s, t)| (s.profile.as_str(), t))
+    };
+    assert_eq!(
+        due("2024-01-01T07:00:00Z", "2024-01-02T00:00:00Z"),
+        Some(("night", at("2024-01-01T23:00:00Z")))
+    );
+    assert_eq!(
+        due("2024-01-01T22:00:00Z", "2024-01-02T09:00:00Z"),
+        Some(("day", at("2024-01-02T08:00:00Z")))
+    );
+    assert_eq!(due("2024-01-01T09:00:00Z", "2024-01-01T22:00:00Z"), None);
+}
+
+#[tokio::test]
+async fn tweet_trigger_skips_schedule_in_same_cycle() {
+    let client = FakeClient::new();
+    let account = scheduled_account(&[("* * * * *", "night")]).unwrap();
+    let mut state = state_after(10);
+    state.last_schedule_check = Some(Utc::now() - Duration::minutes(5));
+    client.tweet(11, "day");
+
+    poll(&client, &account, &mut state).await;
+
+    assert_eq!(profile_updates(&client).len(), 1);
+    assert_eq!(state.applied_profile.as_deref(), Some("day"));
+
+    // the schedule fires without the trigger
+    state.last_schedule_check = Some(Utc::now() - Duration::minutes(5));
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+}
+
+#[test]
+fn state_file_keeps_default_account_at_top_level() {
+    let mut file: StateFile = serde_yaml::from_str("last_status_id: 10\n").unwrap();
+    assert_eq!(
+        file.account_mut(DEFAULT_ACCOUNT_KEY).last_status_id,
+        Some(10)
+    );
+    assert_eq!(file.account_mut("team").last_status_id, None);
+
+    file.account_mut("team").last_status_id = Some(20);
+    let yaml = serde_yaml::to_string(&file).unwrap();
+    let reloaded: StateFile = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(reloaded, file);
+    assert!(yaml.contains("last_status_id: 10"));
+}
+
+const TWO_ACCOUNTS: &str = r#"
+auth_info: { user_id: 1, token: t1, secret: s1 }
+property: { trigger_retweet: false, trigger_quote: false, trigger_reply: false }
+profiles:
+  - { key: swap, matches: [], triggers: [swap], name: companion, target: bot }
+accounts:
+  - key: bot
+    auth_info: { user_id: 2, token: t2, secret: s2 }
+"#;
+
+#[tokio::test]
+async fn failing_account_does_not_stop_the_others() {
+    let client = FakeClient::new();
+    let config: Config = serde_yaml::from_str(
+        r#"
+auth_info: { user_id: 1, token: t1, secret: s1 }
+property: { trigger_retweet: false, trigger_quote: false, trigger_reply: false, receive_interval: 10 }
+profiles:
+  - { key: day, triggers: [day], name: day }
+accounts:
+  - key: bot
+    auth_info: { user_id: 2, token: t2, secret: s2 }
+    profiles:
+      - { key: day, triggers: [day], name: bot day }
+"#,
+    )
+    .unwrap();
+    let config = config.validate_offline().unwrap();
+    let mut state = StateFile::default();
+    for key in [DEFAULT_ACCOUNT_KEY, "bot"].iter() {
+        state.account_mut(key).last_status_id = Some(10);
+    }
+    let mut pacers = HashMap::new();
+    client.fail_for(2, "user_timeline");
+    client.tweet(11, "day");
+
+    let now = Utc::now();
+    assert!(poll_accounts(&client, &config, &mut state, &mut pacers, now).await);
+    assert_eq!(
+        state
+            .account_mut(DEFAULT_ACCOUNT_KEY)
+            .applied_profile
+            .as_deref(),
+        Some("day")
+    );
+    assert_eq!(state.account_mut("bot").applied_profile, None);
+    assert_eq!(state.account_mut("bot").last_status_id, Some(10));
+    let base = Duration::seconds(10);
+    assert_eq!(pacers[DEFAULT_ACCOUNT_KEY].next_interval(base, now), base);
+    assert!(pacers["bot"].next_interval(base, now) > base);
+
+    // the failing one catches up after backing off
+    client.recover("user_timeline");
+    let later = now + Duration::minutes(1);
+    assert!(poll_accounts(&client, &config, &mut state, &mut pacers, later).await);
+    assert_eq!(
+        state.account_mut("bot").applied_profile.as_deref(),
+        Some("day")
+    );
+    assert!(client.calls_by(2).contains(&Call::UpdateProfile {
+        name: Some("bot day".to_string()),
+        url: None,
+        location: None,
+        description: None,
+    }));
+}
+
+#[tokio::test]
+async fn profile_is_applied_to_target_account() {
+    let client = FakeClient::new();
+    let config: Config = serde_yaml::from_str(TWO_ACCOUNTS).unwrap();
+    let config = config.validate(&client).await.unwrap();
+    let account = config.account(DEFAULT_ACCOUNT_KEY).unwrap();
+    let mut state = state_after(10);
+    client.tweet(11, "swap");
+
+    poll(&client, account, &mut state).await;
+
+    assert!(client.calls_by(2).contains(&Call::UpdateProfile {
+        name: Some("companion".to_string()),
+        url: None,
+        location: None,
+        description: None,
+    }));
+    assert_eq!(profile_updates(&client).len(), 1);
+    // the face of the triggered account is kept
+    assert_eq!(state.applied_profile, None);
+}
+
+#[test]
+fn unknown_target_account_is_rejected() {
+    let yaml = TWO_ACCOUNTS.replace("target: bot", "target: nobody");
+    let config: Config = serde_yaml::from_str(&yaml).unwrap();
+    assert!(config.validate_offline().is_err());
+}
+
+#[tokio::test]
+async fn mention_from_allowed_user_triggers() {
+    let client = FakeClient::new();
+    let night = Profile {
+        sources: vec![Source::Mentions],
+        ..profile("night", "night")
+    };
+    let account = account_with(
+        Property {
+            mention_users: vec!["@Teammate".to_string()],
+            ..Property::create_sample()
+        },
+        vec![night],
+    );
+    let mut state = State {
+        last_mention_id: Some(10),
+        ..state_after(10)
+    };
+    client.push_mention(status_json_by(11, "@twentyfaces  night", 2, "teammate"));
+    client.push_mention(status_json_by(12, "@twentyfaces night", 3, "stranger"));
+    // own tweets are not watched without the profiles of the source
+    client.tweet(13, "night");
+
+    poll(&client, &account, &mut state).await;
+
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+    assert!(client
+        .calls()
+        .contains(&Call::MentionsTimeline { since_id: Some(10) }));
+    assert_eq!(state.last_mention_id, Some(12));
+    assert_eq!(state.last_status_id, Some(10));
+}
+
+#[tokio::test]
+async fn first_mention_after_empty_timeline_triggers() {
+    let client = FakeClient::new();
+    let account = account_with(
+        Property {
+            mention_users: vec!["@Teammate".to_string()],
+            ..Property::create_sample()
+        },
+        vec![Profile {
+            sources: vec![Source::Mentions],
+            ..profile("night", "night")
+        }],
+    );
+    let mut state = State::default();
+    poll(&client, &account, &mut state).await;
+    assert!(state.last_mention_id.is_some());
+
+    client.push_mention(status_json_by(11, "@twentyfaces night", 2, "teammate"));
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+    assert_eq!(state.last_mention_id, Some(11));
+    // the placeholder of the empty timeline is not sent
+    assert!(!client
+        .calls()
+        .contains(&Call::MentionsTimeline { since_id: Some(0) }));
+}
+
+#[tokio::test]
+async fn home_timeline_is_polled_only_when_used() {
+    let client = FakeClient::new();
+    let account = account(vec![profile("night", "night")]);
+    let mut state = state_after(10);
+
+    poll(&client, &account, &mut state).await;
+    assert!(!client
+        .calls()
+        .iter()
+        .any(|c| matches!(c, Call::HomeTimeline { .. } | Call::MentionsTimeline { .. })));
+
+    let day = Profile {
+        sources: vec![Source::Home],
+        ..profile("day", "good morning")
+    };
+    let account = account_with(
+        Property {
+            mention_users: vec!["@Teammate".to_string()],
+            ..Property::create_sample()
+        },
+        vec![day],
+    );
+    // the first fetch is dry-run
+    client.push_home(status_json_by(11, "good morning", 2, "friend"));
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile, None);
+    client.push_home(status_json_by(12, "good morning", 2, "friend"));
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("day"));
+}
+
+fn direct_replies(client: &FakeClient) -> Vec<String> {
+    client
+        .calls()
+        .into_iter()
+        .filter_map(|c| match c {
+            Call::SendDirectMessage {
+                recipient_id: 2,
+                text,
+            } => Some(text),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn direct_message_commands_are_parsed() {
+    assert_eq!(Command::parse("apply night"), Some(Command::Apply("night")));
+    assert_eq!(Command::parse(" Status "), Some(Command::Status));
+    assert_eq!(Command::parse("apply"), None);
+    assert_eq!(Command::parse("pause now"), None);
+}
+
+#[tokio::test]
+async fn first_command_after_empty_history_is_run() {
+    let client = FakeClient::new();
+    let account = account_with(
+        Property {
+            dm_users: vec![2],
+            ..Property::create_sample()
+        },
+        vec![profile("night", "night")],
+    );
+    let mut state = state_after(10);
+    poll(&client, &account, &mut state).await;
+    assert!(state.last_direct_message_id.is_some());
+
+    client.push_direct_message(11, 2, 1, "apply night");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+    assert_eq!(direct_replies(&client).len(), 1);
+    assert_eq!(state.last_direct_message_id, Some(11));
+}
+
+#[tokio::test]
+async fn direct_messages_control_the_account() {
+    let client = FakeClient::new();
+    let account = account_with(
+        Property {
+            dm_users: vec![2],
+            ..Property::create_sample()
+        },
+        vec![profile("day", "day"), profile("night", "night")],
+    );
+    let mut state = State {
+        last_direct_message_id: Some(10),
+        ..state_after(10)
+    };
+    client.push_direct_message(11, 2, 1, "apply night");
+    // not allowed
+    client.push_direct_message(12, 3, 1, "apply day");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+
+    client.push_direct_message(14, 2, 1, "apply day");
+    client.push_direct_message(15, 2, 1, "revert");
+    client.push_direct_message(16, 2, 1, "pause");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+    assert_eq!(state.previous_profile.as_deref(), Some("day"));
+    assert!(state.paused);
+
+    // tweets are ignored while paused
+    client.tweet(11, "day");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+    assert_eq!(state.last_status_id, Some(11));
+
+    assert_eq!(
+        direct_replies(&client),
+        [
+            "Profile \"night\" is applied.",
+            "Profile \"day\" is applied.",
+            "Profile \"night\" is applied.",
+            "Paused. Tweets, schedules and expiration are ignored until resume.",
+        ]
+    );
+}
+
+/// the IDs of the statuses destroyed by the fake client.
+fn deletions(client: &FakeClient) -> Vec<u64> {
+    client
+        .calls()
+        .into_iter()
+        .filter_map(|c| match c {
+            Call::DestroyStatus(id) => Some(id),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn trigger_tweet_is_deleted_only_on_whole_match() {
+    let client = FakeClient::new();
+    let delete_trigger = DeleteTrigger {
+        enabled: true,
+        on_failure: FailurePolicy::Warn,
+        allow_partial: false,
+    };
+    let deleting = |delete_trigger, profiles| {
+        account_with(
+            Property {
+                delete_trigger: Some(delete_trigger),
+                ..Property::create_sample()
+            },
+            profiles,
+        )
+    };
+    let account = deleting(
+        delete_trigger.clone(),
+        vec![
+            profile("day", "day"),
+            Profile {
+                matches: vec!["night".to_string()],
+                ..profile("night", "good night")
+            },
+        ],
+    );
+    let mut state = state_after(10);
+    client.tweet(11, "day");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(deletions(&client), [11]);
+
+    // the pattern matched only a part of the normal tweet
+    client.tweet(12, "I will be up all night");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+    assert_eq!(deletions(&client), [11]);
+
+    let account = deleting(
+        DeleteTrigger {
+            allow_partial: true,
+            ..delete_trigger
+        },
+        account.profiles().to_vec(),
+    );
+    client.tweet(13, "working all night");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(deletions(&client), [11, 13]);
+}
+
+#[tokio::test]
+async fn failed_deletion_is_retried() {
+    let client = FakeClient::new();
+    let account = account_with(
+        Property {
+            delete_trigger: Some(DeleteTrigger {
+                enabled: true,
+                on_failure: FailurePolicy::Retry,
+                allow_partial: false,
+            }),
+            ..Property::create_sample()
+        },
+        vec![profile("day", "day")],
+    );
+    let mut state = state_after(10);
+    client.fail("destroy_status");
+    client.tweet(11, "day");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("day"));
+    assert_eq!(state.pending_deletes, [11]);
+
+    client.recover("destroy_status");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(deletions(&client), [11, 11]);
+    assert!(state.pending_deletes.is_empty());
+}
+
+#[test]
+fn unknown_placeholders_are_kept() {
+    let vars: Variables = [("role".to_string(), "cat".to_string())]
+        .iter()
+        .cloned()
+        .collect();
+    assert_eq!(render("{role} {x} {role", &vars), "cat {x} {role");
+    assert_eq!(render("{{role}}", &vars), "{cat}");
+}
+
+fn role_profile(name: &str) -> Profile {
+    Profile {
+        key: "role".to_string(),
+        matches: vec![r"^I am now (?P<role>\w+)$".to_string()],
+        name: Some(name.to_string()),
+        intro: Some("Now I'm {role}.".to_string()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn captures_fill_profile_fields() {
+    let client = FakeClient::new();
+    let account = account(vec![role_profile("karno the {role}")]);
+    let mut state = state_after(10);
+    client.tweet(11, "I am now wizard");
+    poll(&client, &account, &mut state).await;
+
+    assert_eq!(
+        profile_updates(&client),
+        vec![Call::UpdateProfile {
+            name: Some("karno the wizard".to_string()),
+            url: None,
+            location: None,
+            description: None,
+        }]
+    );
+    assert!(client.calls().contains(&Call::UpdateStatus {
+        status: "Now I'm wizard.".to_string(),
+        in_reply_to_status_id: Some(11),
+    }));
+}
+
+#[tokio::test]
+async fn case_folded_pattern_captures_as_written() {
+    let client = FakeClient::new();
+    let account = account(vec![Profile {
+        normalize: Normalization {
+            case_fold: true,
+            ..Default::default()
+        },
+        ..role_profile("karno the {role}")
+    }]);
+    let mut state = state_after(10);
+    client.tweet(11, "i AM NOW Wizard");
+    poll(&client, &account, &mut state).await;
+
+    assert_eq!(state.applied_profile.as_deref(), Some("role"));
+    assert!(client.calls().contains(&Call::UpdateStatus {
+        status: "Now I'm Wizard.".to_string(),
+        in_reply_to_status_id: Some(11),
+    }));
+}
+
+#[tokio::test]
+async fn too_long_field_is_not_applied() {
+    let client = FakeClient::new();
+    let account = account(vec![role_profile("{role}{role}")]);
+    let mut state = state_after(10);
+    client.tweet(11, &format!("I am now {}", "a".repeat(26)));
+    poll(&client, &account, &mut state).await;
+
+    assert!(profile_updates(&client).is_empty());
+    assert_eq!(state.applied_profile, None);
+}
+
+#[tokio::test]
+async fn builtin_variables_fill_profile_fields() {
+    let client = FakeClient::new();
+    let mask = Profile {
+        description: Some("Mask #{count} since {since}".into()),
+        intro: Some("{previous} -> {profile} by {tweet_id}: {tweet}".to_string()),
+        ..profile("mask", "mask on")
+    };
+    let property = Property {
+        timezone: Some("UTC".to_string()),
+        ..Property::create_sample()
+    };
+    let account = &account_with(property, vec![mask, profile("plain", "plain")]);
+    let since = Utc::now() - Duration::days(3);
+    let mut state = State {
+        applied_profile: Some("plain".to_string()),
+        profile_stats: [("mask".to_string(), ProfileStats { count: 4, since })]
+            .iter()
+            .cloned()
+            .collect(),
+        ..state_after(10)
+    };
+    client.tweet(11, "mask on");
+    poll(&client, account, &mut state).await;
+
+    assert!(client.calls().contains(&Call::UpdateProfile {
+        name: Some("mask".to_string()),
+        url: None,
+        location: None,
+        description: Some(format!("Mask #5 since {}", since.format("%Y-%m-%d"))),
+    }));
+    assert!(client.calls().contains(&Call::UpdateStatus {
+        status: "plain -> mask by 11: mask on".to_string(),
+        in_reply_to_status_id: Some(11),
+    }));
+    assert_eq!(state.profile_stats["mask"].count, 5);
+}
+
+#[tokio::test]
+async fn conditions_match_entities_and_client() {
+    let condition: Condition = serde_yaml::from_str(
+        r##"
+all:
+  - hashtag: "#night"
+  - not: {has_media: true}
+  - any:
+    - client: Twitter for iPhone
+    - mention: bot
+"##,
+    )
+    .unwrap();
+    let client = FakeClient::new();
+    let account = account(vec![Profile {
+        condition: Some(condition),
+        triggers: Vec::new(),
+        ..profile("night", "")
+    }]);
+    let mut state = state_after(10);
+    let tweet = |id: u64, media: bool, source: &str| {
+        let mut status = status_json(id, "good #Night");
+        status["entities"] = serde_json::json!({
+            "hashtags": [{"text": "Night"}],
+            "media": if media { serde_json::json!([{"id": 1}]) } else { serde_json::json!([]) },
+        });
+        status["source"] =
+            serde_json::json!(format!("<a href=\"https://example.com\">{}</a>", source));
+        status
+    };
+    client.push_status(tweet(11, true, "Twitter for iPhone"));
+    client.push_status(tweet(12, false, "Twitter Web App"));
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile, None);
+
+    client.push_status(tweet(13, false, "Twitter for iPhone"));
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+}
+
+#[tokio::test]
+async fn reply_condition_overrides_property() {
+    let client = FakeClient::new();
+    let account = account(vec![
+        profile("day", "day"),
+        Profile {
+            condition: Some(Condition::Reply(true)),
+            ..profile("night", "night")
+        },
+    ]);
+    let mut state = state_after(10);
+    let reply = |id: u64, text: &str| {
+        let mut status = status_json(id, text);
+        status["in_reply_to_status_id"] = serde_json::json!(1);
+        status
+    };
+    client.push_status(reply(11, "day"));
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile, None);
+
+    client.push_status(reply(12, "night"));
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+}
+
+#[test]
+fn normalization_ignores_width_case_spaces_and_links() {
+    let normalize = Normalization {
+        nfkc: true,
+        case_fold: true,
+        trim: true,
+        collapse_whitespace: true,
+        strip_urls: true,
+    };
+    assert_eq!(
+        normalize.apply(" Ｇｏｏｄ　 Night https://t.co/abc https://example.com "),
+        "good night"
+    );
+    assert_eq!(
+        Normalization {
+            strip_urls: true,
+            ..Default::default()
+        }
+        .apply("see https://t.co/abc here  https://example.com"),
+        "see here"
+    );
+    assert_eq!(Normalization::default().apply(" As Is "), " As Is ");
+}
+
+#[tokio::test]
+async fn normalized_triggers_and_patterns_match() {
+    let client = FakeClient::new();
+    let normalize = Normalization {
+        nfkc: true,
+        case_fold: true,
+        trim: true,
+        ..Default::default()
+    };
+    let account = account(vec![
+        Profile {
+            normalize: normalize.clone(),
+            ..profile("night", "Good Night")
+        },
+        Profile {
+            normalize,
+            matches: vec!["^Morning".to_string()],
+            ..profile("day", "")
+        },
+    ]);
+    let mut state = state_after(10);
+    client.tweet(11, "ｇｏｏｄ ｎｉｇｈｔ ");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+
+    client.tweet(12, "MORNING!");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("day"));
+}
+
+fn prioritized(key: &str, trigger: &str, priority: i32) -> Profile {
+    Profile {
+        priority,
+        ..profile(key, trigger)
+    }
+}
+
+/// "any" is triggered by "day" with the highest priority.
+fn prioritized_profiles() -> Vec<Profile> {
+    vec![
+        prioritized("day", "day", 0),
+        prioritized("night", "night", 1),
+        prioritized("any", "day", 5),
+    ]
+}
+
+#[tokio::test]
+async fn priority_breaks_ties_in_a_tweet() {
+    let client = FakeClient::new();
+    let account = account_with(
+        Property {
+            batch_policy: BatchPolicy::Newest,
+            ..Property::create_sample()
+        },
+        prioritized_profiles(),
+    );
+    let mut state = state_after(10);
+    client.tweet(11, "night");
+    client.tweet(12, "day");
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("any"));
+    assert_eq!(profile_updates(&client).len(), 1);
+}
+
+#[tokio::test]
+async fn batch_policy_chooses_profiles() {
+    let client = FakeClient::new();
+    client.tweet(11, "night");
+    client.tweet(12, "night");
+    client.tweet(13, "day");
+    client.tweet(14, "night");
+
+    let account = account_with(
+        Property {
+            batch_policy: BatchPolicy::Priority,
+            ..Property::create_sample()
+        },
+        prioritized_profiles(),
+    );
+    let mut state = state_after(10);
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("any"));
+
+    let account = account_with(
+        Property {
+            batch_policy: BatchPolicy::Chronological,
+            ..Property::create_sample()
+        },
+        prioritized_profiles(),
+    );
+    let mut state = state_after(10);
+    poll(&client, &account, &mut state).await;
+    assert_eq!(state.applied_profile.as_deref(), Some("night"));
+    assert_eq!(state.previous_profile.as_deref(), Some("any"));
+    // the profile is applied for each tweet, except for the second "night" which is live already
+    assert_eq!(profile_updates(&client).len(), 1 + 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_banner_rolls_back_image() {
+    let client = FakeClient::new();
+    let dir = temp_dir();
+    let avatar = image_file(&dir, "avatar.png", 400, 400);
+    let banner = image_file(&dir, "banner.png", 1500, 500);
+    let images = Profile {
+        image: Some(avatar.as_str().into()),
+        banner: Some(Clearable::Set(banner.as_str().into())),
+        ..profile("images", "images")
+    };
+    let account = account(vec![images]);
+    // the current face
+    let auth = AuthInfo::default();
+    client.update_profile_image(&auth, "old.png").await.unwrap();
+    let mut state = state_after(10);
+    client.fail("update_profile_banner");
+    client.tweet(11, "images");
+    poll(&client, &account, &mut state).await;
+
+    assert_eq!(state.applied_profile, None);
+    let calls = client.calls();
+    let uploads: Vec<&Call> = calls
+        .iter()
+        .filter(|c| {
+            matches!(
+                c,
+                Call::UpdateProfileImage(_) | Call::UpdateProfileBanner(_)
+            )
+        })
+        .collect();
+    assert_eq!(
+        uploads,
+        [
+            &Call::UpdateProfileImage("old.png".to_string()),
+            &Call::UpdateProfileImage(avatar.clone()),
+            // retried twice
+            &Call::UpdateProfileBanner(banner.clone()),
+            &Call::UpdateProfileBanner(banner.clone()),
+            &Call::UpdateProfileBanner(banner.clone()),
+            &Call::UpdateProfileImage("old.png".to_string()),
+        ]
+    );
+    assert!(profile_updates(&client).is_empty());
+}
+
+#[tokio::test]
+async fn unchanged_fields_and_images_are_not_sent() {
+    let client = FakeClient::new();
+    let dir = temp_dir();
+    let avatar = image_file(&dir, "avatar.png", 400, 400);
+    let banner = image_file(&dir, "banner.png", 1500, 500);
+    let images = Profile {
+        image: Some(avatar.as_str().into()),
+        banner: Some(Clearable::Set(banner.as_str().into())),
+        // seen as "http://example.com"
+        url: Some("example.com".into()),
+        ..profile("images", "images")
+    };
+    let account = account(vec![images]);
+    let resolved = account.profiles()[0].resolve(account.profiles()).unwrap();
+    let mut face = Face::default();
+    let uploads = |client: &FakeClient| {
+        client
+            .calls()
+            .into_iter()
+            .filter(|c| {
+                matches!(
+                    c,
+                    Call::UpdateProfileImage(_)
+                        | Call::UpdateProfileBanner(_)
+                        | Call::UpdateProfile { .. }
+                )
+            })
+            .count()
+    };
+
+    for _ in 0..2 {
+        let report = resolved
+            .apply(
+                &client,
+                &account,
+                None,
+                &Variables::new(),
+                Some(&mut face),
+                &mut BTreeMap::new(),
+            )
+            .await;
+        assert!(report.is_applied());
+    }
+    assert_eq!(uploads(&client), 3);
+    assert!(face.image.is_some() && face.banner.is_some());
+
+    // the image replaced by someone else is uploaded again
+    client
+        .update_profile_image(&AuthInfo::default(), "other.png")
+        .await
+        .unwrap();
+    let report = resolved
+        .apply(
+            &client,
+            &account,
+            None,
+            &Variables::new(),
+            Some(&mut face),
+            &mut BTreeMap::new(),
+        )
+        .await;
+    assert!(report.is_applied());
+    // the replacement, and the upload
+    assert_eq!(uploads(&client), 3 + 2);
+    assert_eq!(
+        client.calls().last(),
+        Some(&Call::UpdateProfileImage(avatar.clone()))
+    );
+}
+
+#[tokio::test]
+async fn image_replaced_after_first_upload_is_uploaded_again() {
+    let client = FakeClient::new();
+    let dir = temp_dir();
+    let avatar = image_file(&dir, "avatar.png", 400, 400);
+    let account = account(vec![Profile {
+        image: Some(avatar.as_str().into()),
+        ..profile("image", "image")
+    }]);
+    let resolved = account.profiles()[0].resolve(account.profiles()).unwrap();
+    let mut face = Face::default();
+    for replaced in [false, true].iter() {
+        if *replaced {
+            client
+                .update_profile_image(&AuthInfo::default(), "other.png")
+                .await
+                .unwrap();
+        }
+        let report = resolved
+            .apply(
+                &client,
+                &account,
+                None,
+                &Variables::new(),
+                Some(&mut face),
+                &mut BTreeMap::new(),
+            )
+            .await;
+        assert!(report.is_applied());
+    }
+    assert_eq!(
+        client.calls().last(),
+        Some(&Call::UpdateProfileImage(avatar.clone()))
+    );
+}
+
+#[test]
+fn images_are_checked_against_limits() {
+    let dir = temp_dir();
+    let processing = ImageProcessing {
+        cache_dir: dir.join("cache").to_string_lossy().into_owned(),
+    };
+    let webp = dir.join("avatar.webp");
+    image::RgbImage::new(400, 400).save(&webp).unwrap();
+    let webp = webp.to_string_lossy();
+    let noise = dir.join("noise.png");
+    image::RgbImage::from_fn(600, 600, |_, _| image::Rgb(rand::random()))
+        .save(&noise)
+        .unwrap();
+    let noise = noise.to_string_lossy();
+    let heic = dir.join("photo.heic");
+    std::fs::write(&heic, b"\0\0\0\x18ftypheic\0\0\0\0mif1heic").unwrap();
+    let heic = heic.to_string_lossy();
+
+    assert!(imaging::check(
+        &image_file(&dir, "a.png", 400, 400),
+        ImageKind::Avatar,
+        None
+    )
+    .is_ok());
+    assert!(imaging::check(&webp, ImageKind::Avatar, None).is_err());
+    assert!(imaging::check(&noise, ImageKind::Avatar, None).is_err());
+    assert!(imaging::check(&noise, ImageKind::Banner, None).is_ok());
+    assert!(imaging::check("missing.png", ImageKind::Avatar, None).is_err());
+    // converted
+    assert!(imaging::check(&webp, ImageKind::Avatar, Some(&processing)).is_ok());
+    assert!(imaging::check(&noise, ImageKind::Avatar, Some(&processing)).is_ok());
+    assert!(imaging::check(&heic, ImageKind::Avatar, Some(&processing)).is_err());
+}
+
+#[test]
+fn images_are_processed_and_cached() {
+    let dir = temp_dir();
+    let processing = ImageProcessing {
+        cache_dir: dir.join("cache").to_string_lossy().into_owned(),
+    };
+    let webp = dir.join("avatar.webp");
+    image::RgbImage::new(600, 300).save(&webp).unwrap();
+    let avatar = imaging::prepare(
+        &webp.to_string_lossy(),
+        ImageKind::Avatar,
+        Some(&processing),
+    )
+    .unwrap();
+    assert!(avatar.ends_with(".jpg"));
+    assert_eq!(image::image_dimensions(&avatar).unwrap(), (300, 300));
+
+    let transparent = dir.join("banner.png");
+    image::RgbaImage::new(300, 300).save(&transparent).unwrap();
+    let transparent = transparent.to_string_lossy();
+    let banner = imaging::prepare(&transparent, ImageKind::Banner, Some(&processing)).unwrap();
+    assert!(banner.ends_with(".png"));
+    assert_eq!(image::image_dimensions(&banner).unwrap(), (1500, 500));
+    // cached
+    std::fs::write(&banner, b"cached").unwrap();
+    assert_eq!(
+        imaging::prepare(&transparent, ImageKind::Banner, Some(&processing)).unwrap(),
+        banner
+    );
+    assert_eq!(std::fs::read(&banner).unwrap(), b"cached");
+
+    // as it is without processing
+    assert_eq!(
+        imaging::prepare(&transparent, ImageKind::Banner, None).unwrap(),
+        transparent
+    );
+}
+
+#[tokio::test]
+async fn cleared_fields_are_removed() {
+    let profiles: Vec<Profile> = serde_yaml::from_str(
+        r#"
+- key: base
+  url: example.com
+  location: Tokyo
+  banner: Cargo.toml
+- key: plain
+  derive: base
+  url: ~
+  location: false
+  banner: false
+"#,
+    )
+    .unwrap();
+    assert_eq!(profiles[1].url, None);
+    assert_eq!(profiles[1].location, Some(Clearable::Clear));
+    assert!(serde_yaml::from_str::<Profile>("key: wrong\nbanner: true\n").is_err());
+
+    let client = FakeClient::new();
+    let auth = AuthInfo::default();
+    client
+        .update_profile_banner(&auth, "old.png")
+        .await
+        .unwrap();
+    client
+        .update_profile(&auth, None, None, Some("Osaka"), None)
+        .await
+        .unwrap();
+    let resolved = profiles[1].resolve(&profiles).unwrap();
+    let account = account(Vec::new());
+    let report = resolved
+        .apply(
+            &client,
+            &account,
+            None,
+            &Variables::new(),
+            None,
+            &mut BTreeMap::new(),
+        )
+        .await;
+    assert!(report.is_applied());
+    assert!(client.calls().contains(&Call::RemoveProfileBanner));
+    assert_eq!(
+        profile_updates(&client)[1..],
+        [Call::UpdateProfile {
+            name: None,
+            url: Some("example.com".to_string()),
+            location: Some("".to_string()),
+            description: None,
+        }]
+    );
+
+    // nothing to remove
+    let report = resolved
+        .apply(
+            &client,
+            &account,
+            None,
+            &Variables::new(),
+            None,
+            &mut BTreeMap::new(),
+        )
+        .await;
+    assert!(report.is_applied());
+    assert_eq!(
+        client
+            .calls()
+            .iter()
+            .filter(|c| **c == Call::RemoveProfileBanner)
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn images_rotate_in_directory() {
+    let sources: Vec<ImageSource> = serde_yaml::from_str(
+        r#"
+- face.png
+- [a.png, b.png]
+- images: [faces]
+  pick: round_robin
+"#,
+    )
+    .unwrap();
+    assert_eq!(sources[0], "face.png".into());
+    assert_eq!(
+        sources[1],
+        ImageSource::List(vec!["a.png".to_string(), "b.png".to_string()])
+    );
+    assert!(matches!(
+        sources[2],
+        ImageSource::Rotation {
+            pick: Pick::RoundRobin,
+            ..
+        }
+    ));
+
+    let dir = temp_dir();
+    for name in ["b.png", "a.png"].iter() {
+        image::RgbImage::new(400, 400).save(dir.join(name)).unwrap();
+    }
+    std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+    let source = ImageSource::Rotation {
+        images: vec![dir.to_string_lossy().into_owned()],
+        pick: Pick::RoundRobin,
+    };
+    assert!(source.check(ImageKind::Avatar, None).is_ok());
+
+    let client = FakeClient::new();
+    let mut turns = BTreeMap::new();
+    let mut picked = Vec::new();
+    for _ in 0..3 {
+        let path = source
+            .fetch(&client, ImageKind::Avatar, None, &mut turns, "rotating")
+            .await
+            .unwrap();
+        picked.push(Path::new(&path).file_name().unwrap().to_owned());
+    }
+    assert_eq!(picked, ["a.png", "b.png", "a.png"]);
+    assert_eq!(turns["rotating.image"], 3);
+
+    let empty_dir = temp_dir();
+    let empty = ImageSource::One(empty_dir.to_string_lossy().into_owned());
+    assert!(empty.check(ImageKind::Avatar, None).is_err());
+}
+
+#[tokio::test]
+async fn web_images_are_cached_with_etag() {
+    let png = |width| {
+        let mut data = Vec::new();
+        image::RgbImage::new(width, width)
+            .write_to(
+                &mut std::io::Cursor::new(&mut data),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        data
+    };
+    // the downloaded file has no extension
+    let dimensions = |path: &str| {
+        image::ImageReader::open(path)
+            .and_then(|r| r.with_guessed_format())
+            .unwrap()
+            .into_dimensions()
+            .unwrap()
+    };
+    let url = "https://images.example.com/face.png";
+    let client = FakeClient::new();
+    client.put_web_image(url, "v1", png(400));
+    let dir = temp_dir();
+    let processing = ImageProcessing {
+        cache_dir: dir.to_string_lossy().into_owned(),
+    };
+    let source = ImageSource::from(url);
+    let fetch = || async {
+        source
+            .fetch(
+                &client,
+                ImageKind::Avatar,
+                Some(&processing),
+                &mut BTreeMap::new(),
+                "web",
+            )
+            .await
+    };
+
+    let path = fetch().await.unwrap();
+    assert_eq!(dimensions(&path), (400, 400));
+    assert_eq!(fetch().await.unwrap(), path);
+    client.put_web_image(url, "v2", png(200));
+    assert_eq!(fetch().await.unwrap(), path);
+    assert_eq!(dimensions(&path), (200, 200));
+    let etags: Vec<Option<String>> = client
+        .calls()
+        .into_iter()
+        .filter_map(|c| match c {
+            Call::FetchImage { etag, .. } => Some(etag),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        etags,
+        [None, Some("v1".to_string()), Some("v1".to_string())]
+    );
+
+    // the cached one is used while the web is down
+    client.fail("fetch_image");
+    assert_eq!(fetch().await.unwrap(), path);
+    let missing = ImageSource::from("https://images.example.com/missing.png");
+    assert!(missing
+        .fetch(
+            &client,
+            ImageKind::Avatar,
+            Some(&processing),
+            &mut BTreeMap::new(),
+            "web"
+        )
+        .await
+        .is_err());
+}
+
+#[test]
+fn fetched_image_replaces_cache_with_its_etag() {
+    let dir = temp_dir();
+    let path = dir.join("face");
+    assert_eq!(misc::cached_etag(&path), None);
+    misc::save_fetched(&path, b"v1", Some("\"v1\"")).unwrap();
+    assert_eq!(misc::cached_etag(&path).as_deref(), Some("\"v1\""));
+
+    // the old ETag does not pair with the new file
+    misc::save_fetched(&path, b"v2", None).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"v2");
+    assert_eq!(misc::cached_etag(&path), None);
+    assert!(!path.with_extension("part").exists());
+}
+
+#[tokio::test]
+async fn snapshot_is_kept_apart_from_configuration() {
+    let client = FakeClient::new();
+    let dir = temp_dir();
+    let avatar = image_file(&dir, "avatar.png", 400, 400);
+    let config_path = dir.join("config.yaml");
+    let yaml = format!(
+        r##"# my faces
+auth_info: {{ user_id: 1, token: t1, secret: s1 }}
+property: {{ trigger_retweet: false, trigger_quote: false, trigger_reply: false }}
+profiles:
+  - {{ key: original, name: karno, image: {} }}
+  - {{ key: masked, name: masked, ttl: 60, revert_to: unmask }}
+"##,
+        avatar
+    );
+    std::fs::write(&config_path, &yaml).unwrap();
+    let state_path = dir.join("state.yaml");
+    let state_path = state_path.to_str().unwrap();
+    let open = || Config::open(&config_path).unwrap().validate_offline();
+
+    // the reference to the snapshot is invalid until it is taken
+    assert!(open().is_err());
+    let config: Config =
+        serde_yaml::from_str(&yaml.replace(", ttl: 60, revert_to: unmask", "")).unwrap();
+    let config = config.validate_offline().unwrap();
+    let account = config.account(DEFAULT_ACCOUNT_KEY).unwrap();
+    commands::apply(
+        &client,
+        state_path,
+        account,
+        StateFile::default(),
+        "original",
+    )
+    .await;
+    let snapshot_dir = snapshot::snapshot_dir(&config_path, DEFAULT_ACCOUNT_KEY);
+    commands::take_snapshot(
+        &client,
+        &snapshot_dir,
+        &config,
+        DEFAULT_ACCOUNT_KEY,
+        &State::default(),
+    )
+    .await;
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), yaml);
+
+    let config = open().unwrap();
+    let account = config.account(DEFAULT_ACCOUNT_KEY).unwrap();
+    let unmask = account.profiles().last().unwrap();
+    assert_eq!(unmask.key, snapshot::UNMASK_PROFILE_KEY);
+    assert!(unmask.snapshot);
+    assert_eq!(unmask.name.as_deref(), Some("karno"));
+    assert_eq!(unmask.image, Some(avatar.as_str().into()));
+    assert_eq!(unmask.banner, Some(Clearable::Clear));
+
+    commands::apply(&client, state_path, account, StateFile::default(), "masked").await;
+    commands::unmask(&client, state_path, account, StateFile::default()).await;
+    assert!(matches!(
+        profile_updates(&client).last(),
+        Some(Call::UpdateProfile { name: Some(name), .. }) if name == "karno"
+    ));
+
+    // the own profile "unmask" wins, and is not replaced
+    let own = yaml.replace("key: original", "key: unmask");
+    std::fs::write(&config_path, &own).unwrap();
+    let config = open().unwrap();
+    let unmask = &config.account(DEFAULT_ACCOUNT_KEY).unwrap().profiles()[0];
+    assert_eq!(unmask.key, snapshot::UNMASK_PROFILE_KEY);
+    assert!(!unmask.snapshot);
+    let calls = client.calls().len();
+    commands::take_snapshot(
+        &client,
+        &snapshot_dir,
+        &config,
+        DEFAULT_ACCOUNT_KEY,
+        &State::default(),
+    )
+    .await;
+    assert_eq!(client.calls().len(), calls);
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), own);
+}
+
+#[test]
+fn simulator_reads_texts_and_timeline_json() {
+    let account = account_with(
+        Property {
+            mention_users: vec!["@teammate".to_string()],
+            ..Property::create_sample()
+        },
+        vec![
+            profile("day", "day"),
+            Profile {
+                sources: vec![Source::Mentions],
+                ..profile("night", "night")
+            },
+        ],
+    );
+    let keys = |payload: &str, source| {
+        simulator::evaluate(&account, payload, source)
+            .unwrap()
+            .into_iter()
+            .map(|(text, m)| (text, m.map(|m| m.profile.key.clone())))
+            .collect::<Vec<_>>()
+    };
+    let triggered = |text: &str, key: Option<&str>| (text.to_string(), key.map(String::from));
+
+    // the texts are evaluated regardless of the source
+    assert_eq!(
+        keys("day\n\nnight\nhello\n", Source::Own),
+        [
+            triggered("day", Some("day")),
+            triggered("night", Some("night")),
+            triggered("hello", None),
+        ]
+    );
+
+    let timeline = serde_json::json!([
+        status_json_by(12, "@twentyfaces night", 2, "teammate"),
+        status_json(11, "day"),
+    ])
+    .to_string();
+    assert_eq!(
+        keys(&timeline, Source::Own),
+        [
+            triggered("@twentyfaces night", None),
+            triggered("day", Some("day")),
+        ]
+    );
+    assert_eq!(
+        keys(&timeline, Source::Mentions),
+        [
+            triggered("@twentyfaces night", Some("night")),
+            triggered("day", None),
+        ]
+    );
+    assert!(simulator::evaluate(&account, "[{}]", Source::Own).is_err());
+}
@@ -0,0 +1,215 @@
+use regex::Regex;
+use std::fmt;
+
+use crate::condition::{Condition, Features};
+use crate::config::{Account, Profile, Source};
+use crate::template::Variables;
+use crate::twitter_api::models::Status;
+
+/// the profile triggered by the status, and the rule which matched it.
+#[derive(Debug)]
+pub struct TriggerMatch<'a> {
+    pub profile: &'a Profile,
+    pub rule: MatchedRule<'a>,
+    /// the named groups captured by the pattern
+    pub captures: Variables,
+}
+
+#[derive(Debug)]
+pub enum MatchedRule<'a> {
+    Trigger(&'a str),
+    Pattern(&'a Regex),
+    /// only `condition` of the profile
+    Condition,
+}
+
+impl MatchedRule<'_> {
+    /// whether the rule matched the whole text, not only a part of it.
+    pub fn matches_whole(&self, text: &str) -> bool {
+        match self {
+            MatchedRule::Trigger(_) => true,
+            // the text itself may be a normal tweet
+            MatchedRule::Condition => false,
+            // the leftmost-first match may be shorter than the longest one, which errs on the
+            // side of keeping the tweet.
+            MatchedRule::Pattern(p) => p
+                .find(text)
+                .map(|m| m.start() == 0 && m.end() == text.len())
+                .unwrap_or(false),
+        }
+    }
+}
+
+impl fmt::Display for MatchedRule<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchedRule::Trigger(t) => write!(f, "trigger \"{}\"", t),
+            MatchedRule::Pattern(p) => write!(f, "match /{}/", p),
+            MatchedRule::Condition => write!(f, "condition"),
+        }
+    }
+}
+
+/// the profile with the highest priority triggered by the status.
+pub fn check_triggered_profile<'a>(
+    status: &Status,
+    source: Source,
+    account: &'a Account,
+) -> Option<TriggerMatch<'a>> {
+    find_triggered_profiles(status, source, account)
+        .into_iter()
+        .next()
+}
+
+/// all profiles triggered by the status, in the descending order of the priority.
+pub fn find_triggered_profiles<'a>(
+    status: &Status,
+    source: Source,
+    account: &'a Account,
+) -> Vec<TriggerMatch<'a>> {
+    let mut text = status.text.as_str();
+    match source {
+        Source::Own => {}
+        Source::Mentions => {
+            if !account
+                .property()
+                .allows_mention_from(&status.user.screen_name)
+            {
+                return Vec::new();
+            }
+            // "@account command" is matched as "command"
+            text = strip_leading_mentions(text);
+        }
+        Source::Home => {
+            if status.user.id == account.auth_info().user_id {
+                // own tweets are the source "own"
+                return Vec::new();
+            }
+        }
+    }
+    let property = account.property();
+    let features = Features::of_status(status);
+    // the flags of the property are overridden by the conditions of the profile
+    let allows = |p: &Profile, flag: fn(&Condition) -> bool| {
+        p.condition.as_ref().is_some_and(|c| c.refers(flag))
+    };
+    let excluded = |p: &Profile| {
+        // this is retweet
+        (features.retweet
+            && !property.trigger_retweet
+            && !allows(p, |c| matches!(c, Condition::Retweet(_))))
+            // this is quoted tweet
+            || (features.quote
+                && !property.trigger_quote
+                && !allows(p, |c| matches!(c, Condition::Quote(_))))
+            // this is reply
+            || (features.reply
+                && source != Source::Mentions
+                && !property.trigger_reply
+                && !allows(p, |c| matches!(c, Condition::Reply(_))))
+    };
+    find_profiles(text, account, &features, |p| {
+        p.accepts(source) && !excluded(p)
+    })
+}
+
+/// find the profile triggered by the text, regardless of the kind of the status.
+pub fn check_triggered_profile_text<'a>(
+    text: &str,
+    account: &'a Account,
+) -> Option<TriggerMatch<'a>> {
+    find_profiles(text, account, &Features::of_text(text), |_| true)
+        .into_iter()
+        .next()
+}
+
+/// the profiles triggered by the text. the ones with the same priority are in the order of
+/// the configuration.
+fn find_profiles<'a, F>(
+    text: &str,
+    account: &'a Account,
+    features: &Features,
+    filter: F,
+) -> Vec<TriggerMatch<'a>>
+where
+    F: Fn(&Profile) -> bool,
+{
+    let mut matches: Vec<TriggerMatch> = account
+        .profiles()
+        .iter()
+        .filter(|p| filter(p))
+        .filter(|p| p.condition.as_ref().is_none_or(|c| c.matches(features)))
+        .filter_map(|profile| {
+            let original = text;
+            let text = profile.normalize.apply(text);
+            let text = text.as_ref();
+            // triggers / patterns should not be resolved.
+            check_with_status(&profile.triggers, text, |trigger, text| {
+                profile.normalize.apply(trigger) == text
+            })
+            .map(|t| TriggerMatch {
+                profile,
+                rule: MatchedRule::Trigger(t.as_str()),
+                captures: Variables::new(),
+            })
+            .or_else(|| {
+                // the patterns are case-insensitive already, and the captures keep the case
+                let text = profile.normalize.apply_keeping_case(original);
+                profile.match_instances.iter().find_map(|pattern| {
+                    check_match(pattern, &text).map(|captures| TriggerMatch {
+                        profile,
+                        rule: MatchedRule::Pattern(pattern),
+                        captures,
+                    })
+                })
+            })
+            .or_else(|| {
+                // the condition alone triggers the profile
+                let condition_only = profile.condition.is_some()
+                    && profile.triggers.is_empty()
+                    && profile.match_instances.is_empty();
+                condition_only.then(|| TriggerMatch {
+                    profile,
+                    rule: MatchedRule::Condition,
+                    captures: Variables::new(),
+                })
+            })
+        })
+        .collect();
+    matches.sort_by_key(|m| std::cmp::Reverse(m.profile.priority));
+    matches
+}
+
+fn check_with_status<'a, T, F>(candidates: &'a [T], text: &str, checker: F) -> Option<&'a T>
+where
+    F: Fn(&T, &str) -> bool,
+{
+    candidates.iter().find(|item| checker(item, text))
+}
+
+/// returns the named groups if matched. the groups not participating in the match are empty.
+fn check_match(pattern: &Regex, text: &str) -> Option<Variables> {
+    let captures = pattern.captures(text)?;
+    Some(
+        pattern
+            .capture_names()
+            .flatten()
+            .map(|name| {
+                let value = captures.name(name).map(|m| m.as_str()).unwrap_or_default();
+                (name.to_string(), value.to_string())
+            })
+            .collect(),
+    )
+}
+
+/// remove the mentions at the head of the text.
+fn strip_leading_mentions(text: &str) -> &str {
+    let mut text = text.trim_start();
+    while text.starts_with('@') {
+        text = text
+            .find(char::is_whitespace)
+            .map(|i| text[i..].trim_start())
+            .unwrap_or_default();
+    }
+    text
+}
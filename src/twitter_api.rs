@@ -3,15 +3,23 @@ use thiserror::Error;
 
 pub mod account;
 pub mod auth;
+pub mod client;
+pub mod direct_messages;
+#[cfg(test)]
+pub mod fake;
 pub mod misc;
 pub mod models;
 pub mod statuses;
 
+use models::RateLimit;
 use reqwest::{self, StatusCode};
 use std::error;
 use std::fmt;
 use std::io;
 
+/// the default endpoint of the Twitter API.
+pub const API_BASE_URL: &str = "https://api.twitter.com";
+
 pub type TwitterResult<T> = std::result::Result<T, TwitterError>;
 
 #[derive(Error, Debug)]
@@ -30,10 +38,24 @@ pub enum TwitterError {
     Twitter(#[from] TwitterAccessError),
 }
 
+impl TwitterError {
+    /// whether the request may succeed if retried later: rate limited, server errors, or network errors.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            TwitterError::Network(_) => true,
+            TwitterError::Twitter(e) => {
+                e.code == StatusCode::TOO_MANY_REQUESTS || e.code.is_server_error()
+            }
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct TwitterAccessError {
     pub code: StatusCode,
     pub message: String,
+    pub rate_limit: Option<RateLimit>,
 }
 
 impl error::Error for TwitterAccessError {
@@ -79,7 +101,7 @@ impl fmt::Display for TwitterDataError {
 }
 
 #[async_trait]
-pub(self) trait CheckSuccess: Sized {
+trait CheckSuccess: Sized {
     async fn check_success(self) -> TwitterResult<Self>;
 }
 
@@ -88,10 +110,12 @@ impl CheckSuccess for reqwest::Response {
     async fn check_success(self) -> TwitterResult<Self> {
         let status = self.status();
         if status.is_client_error() || status.is_server_error() {
+            let rate_limit = RateLimit::from_headers(self.headers());
             let text = self.text().await?;
             Err(TwitterError::Twitter(TwitterAccessError {
                 code: status,
                 message: text,
+                rate_limit,
             }))
         } else {
             Ok(self)
@@ -6,14 +6,32 @@ use tokio_util::codec::{BytesCodec, FramedRead};
 
 use crate::config::{ApiKey, AuthInfo, AuthInfoConfigurer};
 
-use super::{CheckSuccess, TwitterResult};
+use super::{models::User, CheckSuccess, TwitterResult};
+
+pub async fn verify_credentials(
+    base_url: &str,
+    api_key: &ApiKey,
+    user: &AuthInfo,
+) -> TwitterResult<User> {
+    let endpoint = format!("{}/1.1/account/verify_credentials.json", base_url);
+    let secret = api_key.as_secrets().auth_info(user);
+    let resp = reqwest::Client::new()
+        .oauth1(secret)
+        .get(&endpoint)
+        .query(&[("skip_status", "true")])
+        .send()
+        .await?;
+    let body = resp.check_success().await?.text().await?;
+    User::deserialize_json(&body)
+}
 
 pub async fn update_profile_image(
+    base_url: &str,
     api_key: &ApiKey,
     user: &AuthInfo,
     image_path: &str,
-) -> TwitterResult<()> {
-    let endpoint = "https://api.twitter.com/1.1/account/update_profile_image.json";
+) -> TwitterResult<User> {
+    let endpoint = format!("{}/1.1/account/update_profile_image.json", base_url);
     let secret = api_key.as_secrets().auth_info(user);
     // read file
     let file = tokio::fs::File::open(image_path).await?;
@@ -23,15 +41,16 @@ pub async fn update_profile_image(
     let form = multipart::Form::new().part("image", part);
     let resp = reqwest::Client::new()
         .oauth1(secret)
-        .post(endpoint)
+        .post(&endpoint)
         .multipart(form)
         .send()
         .await?;
-    resp.check_success().await?;
-    Ok(())
+    let body = resp.check_success().await?.text().await?;
+    User::deserialize_json(&body)
 }
 
 pub async fn update_profile(
+    base_url: &str,
     api_key: &ApiKey,
     user: &AuthInfo,
     name: Option<&str>,
@@ -39,7 +58,7 @@ pub async fn update_profile(
     location: Option<&str>,
     description: Option<&str>,
 ) -> TwitterResult<()> {
-    let endpoint = "https://api.twitter.com/1.1/account/update_profile.json";
+    let endpoint = format!("{}/1.1/account/update_profile.json", base_url);
     let secret = api_key.as_secrets().auth_info(user);
     let mut form = HashMap::new();
 
@@ -59,7 +78,7 @@ pub async fn update_profile(
 
     let resp = reqwest::Client::new()
         .oauth1(secret)
-        .post(endpoint)
+        .post(&endpoint)
         .form(&form)
         .send()
         .await?;
@@ -68,11 +87,12 @@ pub async fn update_profile(
 }
 
 pub async fn update_profile_banner(
+    base_url: &str,
     api_key: &ApiKey,
     user: &AuthInfo,
     image_path: &str,
 ) -> TwitterResult<()> {
-    let endpoint = "https://api.twitter.com/1.1/account/update_profile_banner.json";
+    let endpoint = format!("{}/1.1/account/update_profile_banner.json", base_url);
     let secret = api_key.as_secrets().auth_info(user);
     // read file
     let file = tokio::fs::File::open(image_path).await?;
@@ -81,10 +101,26 @@ pub async fn update_profile_banner(
     let form = multipart::Form::new().part("banner", part);
     let resp = reqwest::Client::new()
         .oauth1(secret)
-        .post(endpoint)
+        .post(&endpoint)
         .multipart(form)
         .send()
         .await?;
     resp.check_success().await?;
     Ok(())
 }
+
+pub async fn remove_profile_banner(
+    base_url: &str,
+    api_key: &ApiKey,
+    user: &AuthInfo,
+) -> TwitterResult<()> {
+    let endpoint = format!("{}/1.1/account/remove_profile_banner.json", base_url);
+    let secret = api_key.as_secrets().auth_info(user);
+    let resp = reqwest::Client::new()
+        .oauth1(secret)
+        .post(&endpoint)
+        .send()
+        .await?;
+    resp.check_success().await?;
+    Ok(())
+}
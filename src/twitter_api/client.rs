@@ -0,0 +1,217 @@
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ApiKey, AuthInfo};
+
+use super::models::{DirectMessageList, RateLimitStatus, Status, Timeline, User};
+use super::{account, direct_messages, misc, statuses, TwitterResult};
+
+/// the Twitter API operations used by the daemon.
+#[async_trait]
+pub trait TwitterClient: Send + Sync {
+    async fn user_timeline(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+        since_id: Option<u64>,
+    ) -> TwitterResult<Timeline>;
+
+    async fn mentions_timeline(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+        since_id: Option<u64>,
+    ) -> TwitterResult<Timeline>;
+
+    async fn home_timeline(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+        since_id: Option<u64>,
+    ) -> TwitterResult<Timeline>;
+
+    async fn update_profile(
+        &self,
+        user: &AuthInfo,
+        name: Option<&str>,
+        url: Option<&str>,
+        location: Option<&str>,
+        description: Option<&str>,
+    ) -> TwitterResult<()>;
+
+    /// returns the user with the new image.
+    async fn update_profile_image(&self, user: &AuthInfo, image_path: &str) -> TwitterResult<User>;
+
+    async fn update_profile_banner(&self, user: &AuthInfo, image_path: &str) -> TwitterResult<()>;
+
+    async fn remove_profile_banner(&self, user: &AuthInfo) -> TwitterResult<()>;
+
+    async fn update_status(
+        &self,
+        user: &AuthInfo,
+        status: &str,
+        in_reply_to_status_id: Option<u64>,
+    ) -> TwitterResult<Status>;
+
+    async fn destroy_status(&self, user: &AuthInfo, status_id: u64) -> TwitterResult<()>;
+
+    async fn check_user_auth(&self, user: &AuthInfo) -> TwitterResult<()>;
+
+    async fn verify_credentials(&self, user: &AuthInfo) -> TwitterResult<User>;
+
+    async fn rate_limit_status(&self, user: &AuthInfo) -> TwitterResult<RateLimitStatus>;
+
+    async fn direct_messages(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+    ) -> TwitterResult<DirectMessageList>;
+
+    async fn send_direct_message(
+        &self,
+        user: &AuthInfo,
+        recipient_id: u64,
+        text: &str,
+    ) -> TwitterResult<()>;
+
+    /// download the public image. see `misc::download_image`.
+    async fn download_image(&self, url: &str, path_without_ext: &Path) -> TwitterResult<PathBuf>;
+
+    /// download the public image if modified. see `misc::fetch_image`.
+    async fn fetch_image(&self, url: &str, path: &Path) -> TwitterResult<misc::Fetched>;
+}
+
+/// the client which accesses the Twitter API over the network.
+pub struct ApiClient {
+    api_key: ApiKey,
+    base_url: String,
+}
+
+impl ApiClient {
+    /// create the client. `base_url` is usually `API_BASE_URL`.
+    pub fn new(api_key: ApiKey, base_url: impl Into<String>) -> ApiClient {
+        ApiClient {
+            api_key,
+            base_url: base_url.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl TwitterClient for ApiClient {
+    async fn user_timeline(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+        since_id: Option<u64>,
+    ) -> TwitterResult<Timeline> {
+        statuses::user_timeline(&self.base_url, &self.api_key, user, count, since_id).await
+    }
+
+    async fn mentions_timeline(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+        since_id: Option<u64>,
+    ) -> TwitterResult<Timeline> {
+        statuses::mentions_timeline(&self.base_url, &self.api_key, user, count, since_id).await
+    }
+
+    async fn home_timeline(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+        since_id: Option<u64>,
+    ) -> TwitterResult<Timeline> {
+        statuses::home_timeline(&self.base_url, &self.api_key, user, count, since_id).await
+    }
+
+    async fn update_profile(
+        &self,
+        user: &AuthInfo,
+        name: Option<&str>,
+        url: Option<&str>,
+        location: Option<&str>,
+        description: Option<&str>,
+    ) -> TwitterResult<()> {
+        account::update_profile(
+            &self.base_url,
+            &self.api_key,
+            user,
+            name,
+            url,
+            location,
+            description,
+        )
+        .await
+    }
+
+    async fn update_profile_image(&self, user: &AuthInfo, image_path: &str) -> TwitterResult<User> {
+        account::update_profile_image(&self.base_url, &self.api_key, user, image_path).await
+    }
+
+    async fn update_profile_banner(&self, user: &AuthInfo, image_path: &str) -> TwitterResult<()> {
+        account::update_profile_banner(&self.base_url, &self.api_key, user, image_path).await
+    }
+
+    async fn remove_profile_banner(&self, user: &AuthInfo) -> TwitterResult<()> {
+        account::remove_profile_banner(&self.base_url, &self.api_key, user).await
+    }
+
+    async fn update_status(
+        &self,
+        user: &AuthInfo,
+        status: &str,
+        in_reply_to_status_id: Option<u64>,
+    ) -> TwitterResult<Status> {
+        statuses::update(
+            &self.base_url,
+            &self.api_key,
+            user,
+            status,
+            in_reply_to_status_id,
+        )
+        .await
+    }
+
+    async fn destroy_status(&self, user: &AuthInfo, status_id: u64) -> TwitterResult<()> {
+        statuses::destroy(&self.base_url, &self.api_key, user, status_id).await
+    }
+
+    async fn check_user_auth(&self, user: &AuthInfo) -> TwitterResult<()> {
+        misc::check_user_auth(&self.base_url, &self.api_key, user).await
+    }
+
+    async fn verify_credentials(&self, user: &AuthInfo) -> TwitterResult<User> {
+        account::verify_credentials(&self.base_url, &self.api_key, user).await
+    }
+
+    async fn rate_limit_status(&self, user: &AuthInfo) -> TwitterResult<RateLimitStatus> {
+        misc::get_rate_limit_status(&self.base_url, &self.api_key, user).await
+    }
+
+    async fn direct_messages(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+    ) -> TwitterResult<DirectMessageList> {
+        direct_messages::list(&self.base_url, &self.api_key, user, count).await
+    }
+
+    async fn send_direct_message(
+        &self,
+        user: &AuthInfo,
+        recipient_id: u64,
+        text: &str,
+    ) -> TwitterResult<()> {
+        direct_messages::send(&self.base_url, &self.api_key, user, recipient_id, text).await
+    }
+
+    async fn download_image(&self, url: &str, path_without_ext: &Path) -> TwitterResult<PathBuf> {
+        misc::download_image(url, path_without_ext).await
+    }
+
+    async fn fetch_image(&self, url: &str, path: &Path) -> TwitterResult<misc::Fetched> {
+        misc::fetch_image(url, path).await
+    }
+}
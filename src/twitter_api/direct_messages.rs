@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest_oauth1::OAuthClientProvider;
+use serde_json::json;
+
+use crate::config::{ApiKey, AuthInfo, AuthInfoConfigurer};
+
+use super::{
+    models::{DirectMessageList, RateLimit},
+    CheckSuccess, TwitterResult,
+};
+
+/// the direct messages sent and received in the last 30 days, newest first.
+pub async fn list(
+    base_url: &str,
+    api_key: &ApiKey,
+    user: &AuthInfo,
+    count: Option<u32>,
+) -> TwitterResult<DirectMessageList> {
+    let endpoint = format!("{}/1.1/direct_messages/events/list.json", base_url);
+    let secret = api_key.as_secrets().auth_info(user);
+    let mut param = HashMap::new();
+    if let Some(count) = count {
+        param.insert("count", count);
+    }
+
+    let resp = reqwest::Client::new()
+        .oauth1(secret)
+        .get(&endpoint)
+        .query(&param)
+        .send()
+        .await?;
+    let resp = resp.check_success().await?;
+    let rate_limit = RateLimit::from_headers(resp.headers());
+    let body = resp.text().await?;
+    let mut list = DirectMessageList::deserialize_json(&body)?;
+    list.rate_limit = rate_limit;
+    Ok(list)
+}
+
+pub async fn send(
+    base_url: &str,
+    api_key: &ApiKey,
+    user: &AuthInfo,
+    recipient_id: u64,
+    text: &str,
+) -> TwitterResult<()> {
+    let endpoint = format!("{}/1.1/direct_messages/events/new.json", base_url);
+    let secret = api_key.as_secrets().auth_info(user);
+    let payload = json!({
+        "event": {
+            "type": "message_create",
+            "message_create": {
+                "target": { "recipient_id": recipient_id.to_string() },
+                "message_data": { "text": text },
+            },
+        },
+    });
+
+    let resp = reqwest::Client::new()
+        .oauth1(secret)
+        .post(&endpoint)
+        .header(CONTENT_TYPE, "application/json")
+        .body(payload.to_string())
+        .send()
+        .await?;
+    resp.check_success().await?;
+    Ok(())
+}
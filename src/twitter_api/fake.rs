@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use crate::config::AuthInfo;
+
+use super::client::TwitterClient;
+use super::misc::{self, Fetched};
+use super::models::{
+    DirectMessage, DirectMessageList, RateLimit, RateLimitStatus, Status, Timeline, User,
+    USER_TIMELINE_RESOURCE,
+};
+use super::{TwitterAccessError, TwitterError, TwitterResult};
+
+/// the operation called to the fake client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Call {
+    UserTimeline {
+        since_id: Option<u64>,
+    },
+    MentionsTimeline {
+        since_id: Option<u64>,
+    },
+    HomeTimeline {
+        since_id: Option<u64>,
+    },
+    UpdateProfile {
+        name: Option<String>,
+        url: Option<String>,
+        location: Option<String>,
+        description: Option<String>,
+    },
+    UpdateProfileImage(String),
+    UpdateProfileBanner(String),
+    RemoveProfileBanner,
+    UpdateStatus {
+        status: String,
+        in_reply_to_status_id: Option<u64>,
+    },
+    DestroyStatus(u64),
+    CheckUserAuth,
+    VerifyCredentials,
+    RateLimitStatus,
+    DirectMessages,
+    SendDirectMessage {
+        recipient_id: u64,
+        text: String,
+    },
+    DownloadImage(String),
+    FetchImage {
+        url: String,
+        etag: Option<String>,
+    },
+}
+
+/// the in-memory Twitter which records every call.
+#[derive(Default)]
+pub struct FakeClient {
+    timeline: Mutex<Vec<Value>>,
+    mentions: Mutex<Vec<Value>>,
+    home: Mutex<Vec<Value>>,
+    direct_messages: Mutex<Vec<DirectMessage>>,
+    profile: Mutex<Value>,
+    calls: Mutex<Vec<(u64, Call)>>,
+    /// the failing operations, of the user or of everyone
+    failures: Mutex<Vec<(Option<u64>, &'static str)>>,
+    rate_limit: Mutex<Option<RateLimit>>,
+    /// the images on the web, with their ETags
+    web_images: Mutex<HashMap<String, (String, Vec<u8>)>>,
+}
+
+impl FakeClient {
+    pub fn new() -> FakeClient {
+        let client: FakeClient = Default::default();
+        *client.profile.lock().unwrap() = user_json();
+        client
+    }
+
+    /// post the tweet of the user.
+    pub fn tweet(&self, id: u64, text: &str) {
+        self.push_status(status_json(id, text));
+    }
+
+    /// post the status given as JSON.
+    pub fn push_status(&self, status: Value) {
+        self.timeline.lock().unwrap().push(status);
+    }
+
+    /// deliver the status mentioning the user.
+    pub fn push_mention(&self, status: Value) {
+        self.mentions.lock().unwrap().push(status);
+    }
+
+    /// deliver the status into the home timeline of the user.
+    pub fn push_home(&self, status: Value) {
+        self.home.lock().unwrap().push(status);
+    }
+
+    /// send the direct message between the users.
+    pub fn push_direct_message(&self, id: u64, sender_id: u64, recipient_id: u64, text: &str) {
+        self.direct_messages.lock().unwrap().push(DirectMessage {
+            id,
+            sender_id,
+            recipient_id,
+            text: text.to_string(),
+        });
+    }
+
+    /// publish the image at the URL.
+    pub fn put_web_image(&self, url: &str, etag: &str, data: Vec<u8>) {
+        self.web_images
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), (etag.to_string(), data));
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, c)| c.clone())
+            .collect()
+    }
+
+    /// the calls made by the user.
+    pub fn calls_by(&self, user_id: u64) -> Vec<Call> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, _)| *id == user_id)
+            .map(|(_, c)| c.clone())
+            .collect()
+    }
+
+    /// notify the rate limit with the timeline.
+    pub fn set_rate_limit(&self, rate_limit: RateLimit) {
+        *self.rate_limit.lock().unwrap() = Some(rate_limit);
+    }
+
+    /// let the operation (named as the method of `TwitterClient`) fail.
+    pub fn fail(&self, operation: &'static str) {
+        self.failures.lock().unwrap().push((None, operation));
+    }
+
+    /// let the operation fail only for the user.
+    pub fn fail_for(&self, user_id: u64, operation: &'static str) {
+        self.failures
+            .lock()
+            .unwrap()
+            .push((Some(user_id), operation));
+    }
+
+    /// let the operation succeed again.
+    pub fn recover(&self, operation: &'static str) {
+        self.failures
+            .lock()
+            .unwrap()
+            .retain(|(_, o)| *o != operation);
+    }
+
+    fn read_timeline(
+        &self,
+        timeline: &Mutex<Vec<Value>>,
+        count: Option<u32>,
+        since_id: Option<u64>,
+    ) -> TwitterResult<Timeline> {
+        let mut statuses = timeline
+            .lock()
+            .unwrap()
+            .iter()
+            .map(Status::deserialize_json_value)
+            .collect::<TwitterResult<Vec<Status>>>()?;
+        statuses.retain(|s| since_id.map(|id| s.id > id).unwrap_or(true));
+        statuses.sort_by_key(|s| std::cmp::Reverse(s.id));
+        statuses.truncate(count.unwrap_or(20) as usize);
+        Ok(Timeline {
+            statuses,
+            rate_limit: self.rate_limit.lock().unwrap().clone(),
+        })
+    }
+
+    fn record(&self, user: &AuthInfo, operation: &'static str, call: Call) -> TwitterResult<()> {
+        self.calls.lock().unwrap().push((user.user_id, call));
+        let failing = self
+            .failures
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(user_id, o)| *o == operation && user_id.is_none_or(|id| id == user.user_id));
+        if failing {
+            Err(TwitterError::Twitter(TwitterAccessError {
+                code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("{} failed", operation),
+                rate_limit: None,
+            }))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// the URL of the images uploaded to the fake client.
+const FAKE_IMAGE_HOST: &str = "https://pbs.example.com/";
+
+/// JSON of the status posted by the user of the fake client.
+pub fn status_json(id: u64, text: &str) -> Value {
+    json!({
+        "id": id,
+        "text": text,
+        "user": user_json(),
+        "in_reply_to_status_id": null,
+    })
+}
+
+/// JSON of the status posted by another user.
+pub fn status_json_by(id: u64, text: &str, user_id: u64, screen_name: &str) -> Value {
+    let mut status = status_json(id, text);
+    status["user"]["id"] = json!(user_id);
+    status["user"]["screen_name"] = json!(screen_name);
+    status["user"]["name"] = json!(screen_name);
+    status
+}
+
+pub fn user_json() -> Value {
+    json!({
+        "id": 1,
+        "screen_name": "twentyfaces",
+        "name": "twentyfaces",
+        "url": null,
+        "location": "",
+        "description": "",
+        "protected": false,
+        "profile_image_url_https": null,
+        "profile_banner_url": null,
+    })
+}
+
+#[async_trait]
+impl TwitterClient for FakeClient {
+    async fn user_timeline(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+        since_id: Option<u64>,
+    ) -> TwitterResult<Timeline> {
+        self.record(user, "user_timeline", Call::UserTimeline { since_id })?;
+        self.read_timeline(&self.timeline, count, since_id)
+    }
+
+    async fn mentions_timeline(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+        since_id: Option<u64>,
+    ) -> TwitterResult<Timeline> {
+        self.record(
+            user,
+            "mentions_timeline",
+            Call::MentionsTimeline { since_id },
+        )?;
+        self.read_timeline(&self.mentions, count, since_id)
+    }
+
+    async fn home_timeline(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+        since_id: Option<u64>,
+    ) -> TwitterResult<Timeline> {
+        self.record(user, "home_timeline", Call::HomeTimeline { since_id })?;
+        self.read_timeline(&self.home, count, since_id)
+    }
+
+    async fn update_profile(
+        &self,
+        user: &AuthInfo,
+        name: Option<&str>,
+        url: Option<&str>,
+        location: Option<&str>,
+        description: Option<&str>,
+    ) -> TwitterResult<()> {
+        self.record(
+            user,
+            "update_profile",
+            Call::UpdateProfile {
+                name: name.map(String::from),
+                url: url.map(String::from),
+                location: location.map(String::from),
+                description: description.map(String::from),
+            },
+        )?;
+        let mut profile = self.profile.lock().unwrap();
+        for (key, value) in [
+            ("name", name),
+            ("url", url),
+            ("location", location),
+            ("description", description),
+        ] {
+            if let Some(value) = value {
+                profile[key] = json!(value);
+            }
+        }
+        // as Twitter does
+        if let Some(url) = url.filter(|u| !u.is_empty() && !u.contains("://")) {
+            profile["url"] = json!(format!("http://{}", url));
+        }
+        Ok(())
+    }
+
+    async fn update_profile_image(&self, user: &AuthInfo, image_path: &str) -> TwitterResult<User> {
+        self.record(
+            user,
+            "update_profile_image",
+            Call::UpdateProfileImage(image_path.to_string()),
+        )?;
+        let mut profile = self.profile.lock().unwrap();
+        profile["profile_image_url_https"] =
+            json!(format!("{}{}_normal.jpg", FAKE_IMAGE_HOST, image_path));
+        User::deserialize_json_value(&profile)
+    }
+
+    async fn update_profile_banner(&self, user: &AuthInfo, image_path: &str) -> TwitterResult<()> {
+        self.record(
+            user,
+            "update_profile_banner",
+            Call::UpdateProfileBanner(image_path.to_string()),
+        )?;
+        self.profile.lock().unwrap()["profile_banner_url"] =
+            json!(format!("{}{}", FAKE_IMAGE_HOST, image_path));
+        Ok(())
+    }
+
+    async fn remove_profile_banner(&self, user: &AuthInfo) -> TwitterResult<()> {
+        self.record(user, "remove_profile_banner", Call::RemoveProfileBanner)?;
+        self.profile.lock().unwrap()["profile_banner_url"] = Value::Null;
+        Ok(())
+    }
+
+    async fn update_status(
+        &self,
+        user: &AuthInfo,
+        status: &str,
+        in_reply_to_status_id: Option<u64>,
+    ) -> TwitterResult<Status> {
+        self.record(
+            user,
+            "update_status",
+            Call::UpdateStatus {
+                status: status.to_string(),
+                in_reply_to_status_id,
+            },
+        )?;
+        let mut timeline = self.timeline.lock().unwrap();
+        let id = timeline
+            .iter()
+            .filter_map(|s| s["id"].as_u64())
+            .max()
+            .unwrap_or_default()
+            + 1;
+        let mut posted = status_json(id, status);
+        posted["in_reply_to_status_id"] = json!(in_reply_to_status_id);
+        timeline.push(posted.clone());
+        Status::deserialize_json_value(&posted)
+    }
+
+    async fn destroy_status(&self, user: &AuthInfo, status_id: u64) -> TwitterResult<()> {
+        self.record(user, "destroy_status", Call::DestroyStatus(status_id))?;
+        self.timeline
+            .lock()
+            .unwrap()
+            .retain(|s| s["id"].as_u64() != Some(status_id));
+        Ok(())
+    }
+
+    async fn check_user_auth(&self, user: &AuthInfo) -> TwitterResult<()> {
+        self.record(user, "check_user_auth", Call::CheckUserAuth)
+    }
+
+    async fn verify_credentials(&self, user: &AuthInfo) -> TwitterResult<User> {
+        self.record(user, "verify_credentials", Call::VerifyCredentials)?;
+        User::deserialize_json_value(&self.profile.lock().unwrap())
+    }
+
+    async fn rate_limit_status(&self, user: &AuthInfo) -> TwitterResult<RateLimitStatus> {
+        self.record(user, "rate_limit_status", Call::RateLimitStatus)?;
+        let mut status = RateLimitStatus::default();
+        if let Some(ref rate_limit) = *self.rate_limit.lock().unwrap() {
+            status
+                .resources
+                .insert(USER_TIMELINE_RESOURCE.to_string(), rate_limit.clone());
+        }
+        Ok(status)
+    }
+
+    async fn direct_messages(
+        &self,
+        user: &AuthInfo,
+        count: Option<u32>,
+    ) -> TwitterResult<DirectMessageList> {
+        self.record(user, "direct_messages", Call::DirectMessages)?;
+        let mut messages = self.direct_messages.lock().unwrap().clone();
+        messages.sort_by_key(|m| std::cmp::Reverse(m.id));
+        messages.truncate(count.unwrap_or(20) as usize);
+        Ok(DirectMessageList {
+            messages,
+            rate_limit: None,
+        })
+    }
+
+    async fn send_direct_message(
+        &self,
+        user: &AuthInfo,
+        recipient_id: u64,
+        text: &str,
+    ) -> TwitterResult<()> {
+        self.record(
+            user,
+            "send_direct_message",
+            Call::SendDirectMessage {
+                recipient_id,
+                text: text.to_string(),
+            },
+        )?;
+        let mut messages = self.direct_messages.lock().unwrap();
+        let id = messages.iter().map(|m| m.id).max().unwrap_or_default() + 1;
+        messages.push(DirectMessage {
+            id,
+            sender_id: user.user_id,
+            recipient_id,
+            text: text.to_string(),
+        });
+        Ok(())
+    }
+
+    /// nothing is written. the path is the one which the uploaded image had.
+    async fn download_image(&self, url: &str, _path_without_ext: &Path) -> TwitterResult<PathBuf> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push((0, Call::DownloadImage(url.to_string())));
+        let path = url
+            .trim_start_matches(FAKE_IMAGE_HOST)
+            .trim_end_matches("_normal.jpg")
+            .trim_end_matches(".jpg")
+            .trim_end_matches("/1500x500");
+        Ok(PathBuf::from(path))
+    }
+
+    async fn fetch_image(&self, url: &str, path: &Path) -> TwitterResult<Fetched> {
+        let etag = misc::cached_etag(path);
+        self.record(
+            &AuthInfo::default(),
+            "fetch_image",
+            Call::FetchImage {
+                url: url.to_string(),
+                etag: etag.clone(),
+            },
+        )?;
+        let (current, data) = match self.web_images.lock().unwrap().get(url) {
+            Some(image) => image.clone(),
+            None => {
+                return Err(TwitterError::Twitter(TwitterAccessError {
+                    code: StatusCode::NOT_FOUND,
+                    message: format!("{} is not found", url),
+                    rate_limit: None,
+                }))
+            }
+        };
+        if etag.as_deref() == Some(current.as_str()) {
+            return Ok(Fetched::NotModified);
+        }
+        misc::save_fetched(path, &data, Some(&current))?;
+        Ok(Fetched::Modified)
+    }
+}
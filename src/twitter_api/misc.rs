@@ -1,29 +1,132 @@
+use super::models::RateLimitStatus;
 use super::*;
 use crate::config::*;
 use reqwest;
 use reqwest_oauth1::OAuthClientProvider;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
 // use reqwest_oauth1::client::OAuthClientProvider;
 
-pub async fn check_api_key(api_key: &ApiKey) -> TwitterResult<()> {
-    get_rate_limit_status_core(api_key, None).await
+pub async fn check_api_key(base_url: &str, api_key: &ApiKey) -> TwitterResult<()> {
+    get_rate_limit_status_core(base_url, api_key, None).await?;
+    Ok(())
+}
+
+pub async fn check_user_auth(
+    base_url: &str,
+    api_key: &ApiKey,
+    user: &AuthInfo,
+) -> TwitterResult<()> {
+    get_rate_limit_status(base_url, api_key, user).await?;
+    Ok(())
 }
 
-pub async fn check_user_auth(api_key: &ApiKey, user: &AuthInfo) -> TwitterResult<()> {
-    get_rate_limit_status_core(api_key, Some(user)).await
+pub async fn get_rate_limit_status(
+    base_url: &str,
+    api_key: &ApiKey,
+    user: &AuthInfo,
+) -> TwitterResult<RateLimitStatus> {
+    get_rate_limit_status_core(base_url, api_key, Some(user)).await
 }
 
 async fn get_rate_limit_status_core(
+    base_url: &str,
     api_key: &ApiKey,
     user: Option<&AuthInfo>,
-) -> TwitterResult<()> {
-    let endpoint = "https://api.twitter.com/1.1/application/rate_limit_status.json";
+) -> TwitterResult<RateLimitStatus> {
+    let endpoint = format!("{}/1.1/application/rate_limit_status.json", base_url);
     let secret = api_key.as_secrets().auth_info_option(user);
 
     let resp = reqwest::Client::new()
         .oauth1(secret)
-        .get(endpoint)
+        .get(&endpoint)
         .send()
         .await?;
-    resp.check_success().await?;
+    let body = resp.check_success().await?.text().await?;
+    RateLimitStatus::deserialize_json(&body)
+}
+
+/// download the (public) file and save it with the extension guessed from its content type.
+/// returns the path of the saved file.
+pub async fn download_image<P: AsRef<Path>>(
+    url: &str,
+    path_without_ext: P,
+) -> TwitterResult<PathBuf> {
+    let resp = reqwest::Client::new().get(url).send().await?;
+    let resp = resp.check_success().await?;
+    let ext = match resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some("image/png") => "png",
+        Some("image/gif") => "gif",
+        _ => "jpg",
+    };
+    let path = path_without_ext.as_ref().with_extension(ext);
+    let body = resp.bytes().await?;
+    let mut file = tokio::fs::File::create(&path).await?;
+    file.write_all(&body).await?;
+    file.flush().await?;
+    Ok(path)
+}
+
+/// the result of fetching the file which may have been cached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fetched {
+    /// the new file is saved
+    Modified,
+    /// the cached file is still valid
+    NotModified,
+}
+
+/// the file keeping the ETag of the file downloaded into `path`.
+fn etag_path(path: &Path) -> PathBuf {
+    path.with_extension("etag")
+}
+
+/// the ETag of the file downloaded into `path`, if both of them are kept.
+pub fn cached_etag(path: &Path) -> Option<String> {
+    if path.exists() {
+        std::fs::read_to_string(etag_path(path)).ok()
+    } else {
+        None
+    }
+}
+
+/// save the downloaded file into `path` with its ETag. the file is replaced by renaming, not to
+/// leave a truncated one, and the old ETag is removed before it not to pair it with the new file.
+pub fn save_fetched(path: &Path, data: &[u8], etag: Option<&str>) -> std::io::Result<()> {
+    let temp = path.with_extension("part");
+    std::fs::write(&temp, data)?;
+    let etag_path = etag_path(path);
+    if etag_path.exists() {
+        std::fs::remove_file(&etag_path)?;
+    }
+    std::fs::rename(&temp, path)?;
+    if let Some(etag) = etag {
+        std::fs::write(&etag_path, etag)?;
+    }
     Ok(())
 }
+
+/// download the (public) file into `path`, unless the one downloaded before is not modified.
+pub async fn fetch_image(url: &str, path: &Path) -> TwitterResult<Fetched> {
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(etag) = cached_etag(path) {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    let resp = request.send().await?;
+    if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    }
+    let resp = resp.check_success().await?;
+    let etag = resp
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let body = resp.bytes().await?;
+    save_fetched(path, &body, etag.as_deref())?;
+    Ok(Fetched::Modified)
+}
@@ -1,21 +1,24 @@
 use super::{TwitterDataError, TwitterResult};
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::header::HeaderMap;
 use serde_json::Value;
-
-#[derive(Debug)]
-pub enum StatusType {
-    PublicStatus,
-    DirectMessageTo(User),
-}
+use std::collections::HashMap;
 
 #[derive(Debug)]
 pub struct Status {
-    pub status_type: StatusType,
     pub id: u64,
     pub user: User,
     pub text: String,
     pub in_reply_to_status_id: Option<u64>,
     pub retweeted_status: Option<Box<Status>>,
     pub quoted_status: Option<Box<Status>>,
+    /// hashtags without "#"
+    pub hashtags: Vec<String>,
+    /// screen names of the mentioned users
+    pub mentions: Vec<String>,
+    pub has_media: bool,
+    /// the name of the client which posted the status, like "Twitter for iPhone"
+    pub client: Option<String>,
 }
 
 #[derive(Debug)]
@@ -23,8 +26,63 @@ pub struct User {
     pub id: u64,
     pub screen_name: String,
     pub name: String,
+    pub url: Option<String>,
+    pub location: Option<String>,
     pub description: String,
-    pub is_protected: bool,
+    pub profile_image_url: Option<String>,
+    pub profile_banner_url: Option<String>,
+    pub url_entities: Vec<UrlEntity>,
+    pub description_url_entities: Vec<UrlEntity>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UrlEntity {
+    pub url: String,
+    pub expanded_url: String,
+}
+
+/// the rate limit of an endpoint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset: DateTime<Utc>,
+}
+
+/// the resource names of the timelines in the rate limit status.
+pub const USER_TIMELINE_RESOURCE: &str = "/statuses/user_timeline";
+pub const MENTIONS_TIMELINE_RESOURCE: &str = "/statuses/mentions_timeline";
+pub const HOME_TIMELINE_RESOURCE: &str = "/statuses/home_timeline";
+pub const DIRECT_MESSAGES_RESOURCE: &str = "/direct_messages/events/list";
+
+/// the payload of `application/rate_limit_status`.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimitStatus {
+    /// rate limits keyed by the endpoint path like "/statuses/user_timeline".
+    pub resources: HashMap<String, RateLimit>,
+}
+
+/// the statuses and the rate limit notified with them.
+#[derive(Debug)]
+pub struct Timeline {
+    pub statuses: Vec<Status>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// the direct message event.
+#[derive(Clone, Debug)]
+pub struct DirectMessage {
+    pub id: u64,
+    pub sender_id: u64,
+    pub recipient_id: u64,
+    pub text: String,
+}
+
+/// the direct messages and the rate limit notified with them.
+#[derive(Debug, Default)]
+pub struct DirectMessageList {
+    pub messages: Vec<DirectMessage>,
+    pub rate_limit: Option<RateLimit>,
 }
 
 impl Status {
@@ -46,32 +104,36 @@ impl Status {
     }
 
     pub fn deserialize_json_value(value: &Value) -> TwitterResult<Self> {
-        let status_type = match value.get("recipient").and_then(|r| r.as_str()) {
-            Some(recipient) => StatusType::DirectMessageTo(User::deserialize_json(recipient)?),
-            None => StatusType::PublicStatus,
-        };
-        let retweeted_status = match status_type {
-            StatusType::PublicStatus => value
-                .get("retweeted_status")
-                .map(|v| Status::deserialize_json_value(v)),
-            StatusType::DirectMessageTo(_) => None,
-        }
-        .map_or(Ok(None), |r| r.map(Box::new).map(Some))?;
-        let quoted_status = match status_type {
-            StatusType::PublicStatus => value
-                .get("quoted_status")
-                .map(|v| Status::deserialize_json_value(v)),
-            StatusType::DirectMessageTo(_) => None,
-        }
-        .map_or(Ok(None), |r| r.map(Box::new).map(Some))?;
+        let retweeted_status = value
+            .get("retweeted_status")
+            .map(Status::deserialize_json_value)
+            .map_or(Ok(None), |r| r.map(Box::new).map(Some))?;
+        let quoted_status = value
+            .get("quoted_status")
+            .map(Status::deserialize_json_value)
+            .map_or(Ok(None), |r| r.map(Box::new).map(Some))?;
         let in_reply_to_status_id = value
             .get("in_reply_to_status_id")
             .and_then(|v| v.as_u64())
             // ensure non-zero value
             .and_then(|v| if v > 0 { Some(v) } else { None });
 
+        let entities = &value["entities"];
+        let read_entities = |key: &str, field: &str| -> Vec<String> {
+            entities[key]
+                .as_array()
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|e| e[field].as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let has_media = [entities, &value["extended_entities"]]
+            .iter()
+            .any(|e| e["media"].as_array().is_some_and(|m| !m.is_empty()));
+
         Ok(Status {
-            status_type,
             id: value.read_value("id")?,
             user: value
                 .get("user")
@@ -81,10 +143,110 @@ impl Status {
             in_reply_to_status_id,
             retweeted_status,
             quoted_status,
+            hashtags: read_entities("hashtags", "text"),
+            mentions: read_entities("user_mentions", "screen_name"),
+            has_media,
+            client: value["source"].as_str().map(client_name),
+        })
+    }
+}
+
+/// the name of the client in `source`, which is a link like `<a href="...">Twitter Web App</a>`.
+fn client_name(source: &str) -> String {
+    match (source.find('>'), source.rfind("</")) {
+        (Some(start), Some(end)) if start < end => source[start + 1..end].to_string(),
+        _ => source.to_string(),
+    }
+}
+
+impl DirectMessage {
+    pub fn deserialize_json_value(value: &Value) -> TwitterResult<Self> {
+        // IDs are given as strings in the events API
+        let read_id = |value: &Value, key: &str| -> TwitterResult<u64> {
+            let id: &str = value.read_value(key)?;
+            Ok(id
+                .parse()
+                .map_err(|_| TwitterDataError::new(key, value.to_string()))?)
+        };
+        let message = &value["message_create"];
+        Ok(DirectMessage {
+            id: read_id(value, "id")?,
+            sender_id: read_id(message, "sender_id")?,
+            recipient_id: read_id(&message["target"], "recipient_id")?,
+            text: message["message_data"].read_value("text")?,
         })
     }
 }
 
+impl DirectMessageList {
+    pub fn deserialize_json(json: &str) -> TwitterResult<Self> {
+        let v: Value = serde_json::from_str(json)?;
+        DirectMessageList::deserialize_json_value(&v)
+    }
+
+    /// read the events of `direct_messages/events/list`, ignoring the other types of events.
+    pub fn deserialize_json_value(value: &Value) -> TwitterResult<Self> {
+        let messages = match value["events"].as_array() {
+            Some(events) => events
+                .iter()
+                .filter(|e| e["type"] == "message_create")
+                .map(DirectMessage::deserialize_json_value)
+                .collect::<TwitterResult<Vec<DirectMessage>>>()?,
+            None => Vec::new(),
+        };
+        Ok(DirectMessageList {
+            messages,
+            rate_limit: None,
+        })
+    }
+}
+
+impl RateLimit {
+    /// read `x-rate-limit-*` headers of the response.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let read = |key: &str| -> Option<u64> { headers.get(key)?.to_str().ok()?.parse().ok() };
+        Some(RateLimit {
+            limit: read("x-rate-limit-limit")?,
+            remaining: read("x-rate-limit-remaining")?,
+            reset: Utc
+                .timestamp_opt(read("x-rate-limit-reset")? as i64, 0)
+                .single()?,
+        })
+    }
+
+    pub fn deserialize_json_value(value: &Value) -> TwitterResult<Self> {
+        let reset: u64 = value.read_value("reset")?;
+        Ok(RateLimit {
+            limit: value.read_value("limit")?,
+            remaining: value.read_value("remaining")?,
+            reset: Utc
+                .timestamp_opt(reset as i64, 0)
+                .single()
+                .ok_or_else(|| TwitterDataError::new("reset", value.to_string()))?,
+        })
+    }
+}
+
+impl RateLimitStatus {
+    pub fn deserialize_json(json: &str) -> TwitterResult<Self> {
+        let v: Value = serde_json::from_str(json)?;
+        RateLimitStatus::deserialize_json_value(&v)
+    }
+
+    pub fn deserialize_json_value(value: &Value) -> TwitterResult<Self> {
+        let mut resources = HashMap::new();
+        // resources: { family: { endpoint: { limit, remaining, reset } } }
+        if let Some(families) = value["resources"].as_object() {
+            for endpoints in families.values().filter_map(|f| f.as_object()) {
+                for (endpoint, limit) in endpoints {
+                    resources.insert(endpoint.clone(), RateLimit::deserialize_json_value(limit)?);
+                }
+            }
+        }
+        Ok(RateLimitStatus { resources })
+    }
+}
+
 impl User {
     pub fn deserialize_json(json: &str) -> TwitterResult<Self> {
         let v: Value = serde_json::from_str(json)?;
@@ -92,12 +254,60 @@ impl User {
     }
 
     pub fn deserialize_json_value(value: &Value) -> TwitterResult<Self> {
+        let entities = &value["entities"];
         Ok(User {
             id: value.read_value("id")?,
             screen_name: value.read_value("screen_name")?,
             name: value.read_value("name")?,
+            url: value.read_value("url")?,
+            location: value.read_value("location")?,
             description: value.read_value("description")?,
-            is_protected: value.read_value("protected")?,
+            profile_image_url: value.read_value("profile_image_url_https")?,
+            profile_banner_url: value.read_value("profile_banner_url")?,
+            url_entities: UrlEntity::deserialize_entities(&entities["url"])?,
+            description_url_entities: UrlEntity::deserialize_entities(&entities["description"])?,
+        })
+    }
+
+    /// URL of the profile with t.co links expanded.
+    pub fn expanded_url(&self) -> Option<String> {
+        self.url
+            .as_ref()
+            .map(|url| UrlEntity::expand(url, &self.url_entities))
+    }
+
+    /// description of the profile with t.co links expanded.
+    pub fn expanded_description(&self) -> String {
+        UrlEntity::expand(&self.description, &self.description_url_entities)
+    }
+
+    /// URL of the profile image in the original size.
+    pub fn original_profile_image_url(&self) -> Option<String> {
+        self.profile_image_url
+            .as_ref()
+            .map(|url| url.replace("_normal.", "."))
+    }
+}
+
+impl UrlEntity {
+    /// read `urls` in the entities object.
+    pub fn deserialize_entities(entities: &Value) -> TwitterResult<Vec<Self>> {
+        match entities.get("urls").and_then(|u| u.as_array()) {
+            Some(urls) => urls.iter().map(UrlEntity::deserialize_json_value).collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn deserialize_json_value(value: &Value) -> TwitterResult<Self> {
+        Ok(UrlEntity {
+            url: value.read_value("url")?,
+            expanded_url: value.read_value("expanded_url")?,
+        })
+    }
+
+    fn expand(text: &str, entities: &[UrlEntity]) -> String {
+        entities.iter().fold(text.to_string(), |text, entity| {
+            text.replace(&entity.url, &entity.expanded_url)
         })
     }
 }
@@ -131,6 +341,16 @@ impl ReadValue<'_, String> for Value {
     }
 }
 
+impl ReadValue<'_, Option<String>> for Value {
+    fn read_value(&self, key: &str) -> TwitterResult<Option<String>> {
+        match &self[key] {
+            Value::Null => Ok(None),
+            Value::String(s) => Ok(Some(s.to_string())),
+            _ => Err(TwitterDataError::new(key, self.to_string()).into()),
+        }
+    }
+}
+
 impl ReadValue<'_, u64> for Value {
     fn read_value(&self, key: &str) -> TwitterResult<u64> {
         Ok(self[key]
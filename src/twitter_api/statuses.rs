@@ -5,15 +5,58 @@ use reqwest_oauth1::OAuthClientProvider;
 use crate::config::{ApiKey, AuthInfo, AuthInfoConfigurer};
 
 use super::TwitterResult;
-use super::{models::Status, CheckSuccess};
+use super::{
+    models::{RateLimit, Status, Timeline},
+    CheckSuccess,
+};
 
 pub async fn home_timeline(
+    base_url: &str,
     api_key: &ApiKey,
     user: &AuthInfo,
     count: Option<u32>,
     since_id: Option<u64>,
-) -> TwitterResult<Vec<Status>> {
-    let endpoint = "https://api.twitter.com/1.1/statuses/home_timeline.json";
+) -> TwitterResult<Timeline> {
+    timeline(base_url, "home_timeline", api_key, user, count, since_id).await
+}
+
+pub async fn user_timeline(
+    base_url: &str,
+    api_key: &ApiKey,
+    user: &AuthInfo,
+    count: Option<u32>,
+    since_id: Option<u64>,
+) -> TwitterResult<Timeline> {
+    timeline(base_url, "user_timeline", api_key, user, count, since_id).await
+}
+
+pub async fn mentions_timeline(
+    base_url: &str,
+    api_key: &ApiKey,
+    user: &AuthInfo,
+    count: Option<u32>,
+    since_id: Option<u64>,
+) -> TwitterResult<Timeline> {
+    timeline(
+        base_url,
+        "mentions_timeline",
+        api_key,
+        user,
+        count,
+        since_id,
+    )
+    .await
+}
+
+async fn timeline(
+    base_url: &str,
+    name: &str,
+    api_key: &ApiKey,
+    user: &AuthInfo,
+    count: Option<u32>,
+    since_id: Option<u64>,
+) -> TwitterResult<Timeline> {
+    let endpoint = format!("{}/1.1/statuses/{}.json", base_url, name);
     let secret = api_key.as_secrets().auth_info(user);
     let mut param = HashMap::new();
     if let Some(count) = count {
@@ -22,38 +65,62 @@ pub async fn home_timeline(
     if let Some(since_id) = since_id {
         param.insert("since_id", since_id);
     }
+
     let resp = reqwest::Client::new()
         .oauth1(secret)
-        .get(endpoint)
+        .get(&endpoint)
         .query(&param)
         .send()
         .await?;
-    let body = resp.check_success().await?.text().await?;
-    Ok(Status::deserialize_timeline(&body)?)
+    let resp = resp.check_success().await?;
+    let rate_limit = RateLimit::from_headers(resp.headers());
+    let body = resp.text().await?;
+    Ok(Timeline {
+        statuses: Status::deserialize_timeline(&body)?,
+        rate_limit,
+    })
 }
 
-pub async fn user_timeline(
+pub async fn update(
+    base_url: &str,
     api_key: &ApiKey,
     user: &AuthInfo,
-    count: Option<u32>,
-    since_id: Option<u64>,
-) -> TwitterResult<Vec<Status>> {
-    let endpoint = "https://api.twitter.com/1.1/statuses/user_timeline.json";
+    status: &str,
+    in_reply_to_status_id: Option<u64>,
+) -> TwitterResult<Status> {
+    let endpoint = format!("{}/1.1/statuses/update.json", base_url);
     let secret = api_key.as_secrets().auth_info(user);
-    let mut param = HashMap::new();
-    if let Some(count) = count {
-        param.insert("count", count as u64);
-    }
-    if let Some(since_id) = since_id {
-        param.insert("since_id", since_id);
+    let mut form = HashMap::new();
+    form.insert("status", status.to_string());
+    if let Some(in_reply_to_status_id) = in_reply_to_status_id {
+        form.insert("in_reply_to_status_id", in_reply_to_status_id.to_string());
+        // mention the author of the replied status automatically
+        form.insert("auto_populate_reply_metadata", "true".to_string());
     }
 
     let resp = reqwest::Client::new()
         .oauth1(secret)
-        .get(endpoint)
-        .query(&param)
+        .post(&endpoint)
+        .form(&form)
         .send()
         .await?;
     let body = resp.check_success().await?.text().await?;
-    Ok(Status::deserialize_timeline(&body)?)
+    Status::deserialize_json(&body)
+}
+
+pub async fn destroy(
+    base_url: &str,
+    api_key: &ApiKey,
+    user: &AuthInfo,
+    status_id: u64,
+) -> TwitterResult<()> {
+    let endpoint = format!("{}/1.1/statuses/destroy/{}.json", base_url, status_id);
+    let secret = api_key.as_secrets().auth_info(user);
+    let resp = reqwest::Client::new()
+        .oauth1(secret)
+        .post(&endpoint)
+        .send()
+        .await?;
+    resp.check_success().await?;
+    Ok(())
 }